@@ -6,6 +6,8 @@
     var ignoreChanges = false;
     var saveTimer = null;
     var indicator = null;
+    var overlay = null;
+    var invoke = null;
 
     function createIndicator() {
         indicator = document.createElement('div');
@@ -14,25 +16,61 @@
             'border-radius:50%;background:#4c4;z-index:999999;' +
             'transition:background 0.3s;pointer-events:none;';
         document.body.appendChild(indicator);
+
+        overlay = document.createElement('div');
+        overlay.style.cssText =
+            'position:fixed;top:28px;right:8px;max-width:480px;max-height:40vh;' +
+            'overflow:auto;padding:8px 12px;border-radius:4px;' +
+            'background:rgba(40,0,0,0.9);color:#fdd;z-index:999999;' +
+            'font:12px/1.4 monospace;white-space:pre-wrap;display:none;';
+        document.body.appendChild(overlay);
     }
 
-    function base64ToArrayBuffer(base64) {
-        var binaryString = atob(base64);
-        var len = binaryString.length;
-        var bytes = new Uint8Array(len);
-        for (var i = 0; i < len; i++) {
-            bytes[i] = binaryString.charCodeAt(i);
+    function formatLocation(diag) {
+        var location = diag.file || '';
+        if (diag.line != null) {
+            location += ':' + diag.line + ':' + diag.column;
+        }
+        if (diag.pointer) {
+            location += ' ' + diag.pointer;
         }
-        return bytes.buffer;
+        return location;
     }
 
-    function arrayBufferToBase64(buffer) {
-        var bytes = new Uint8Array(buffer);
-        var binary = '';
-        for (var i = 0; i < bytes.byteLength; i++) {
-            binary += String.fromCharCode(bytes[i]);
+    // Show why the last workspace edit was rejected
+    function showBuildError(error) {
+        indicator.style.background = '#c44';
+        var lines = ['Build failed: ' + error.message];
+        var diagnostics = error.diagnostics || [];
+        if (diagnostics.length === 0 && error.file) {
+            diagnostics = [error];
         }
-        return btoa(binary);
+        diagnostics.forEach(function (diag) {
+            lines.push(formatLocation(diag) + ': ' + diag.message);
+        });
+        overlay.textContent = lines.join('\n');
+        overlay.style.display = 'block';
+    }
+
+    // The last save from the editor clashed with edits to the workspace
+    // files; the workspace kept its version
+    function showConflict(conflict) {
+        indicator.style.background = '#c84';
+        var lines = ['Your changes conflict with edits to the workspace files.'];
+        if (conflict.snapshot) {
+            lines.push('They were saved as snapshot ' + conflict.snapshot +
+                ' (File > Revert To...).');
+        }
+        conflict.conflicts.forEach(function (pointer) {
+            lines.push(pointer);
+        });
+        overlay.textContent = lines.join('\n');
+        overlay.style.display = 'block';
+    }
+
+    function clearBuildError() {
+        indicator.style.background = '#4c4';
+        overlay.style.display = 'none';
     }
 
     function loadProject(arrayBuffer) {
@@ -45,8 +83,10 @@
             saveTimer = null;
         }
 
+        var started = performance.now();
         window.vm.loadProject(arrayBuffer).then(function () {
             console.log('[live-scratch] project loaded');
+            reportLoad(true, null, started);
             if (editingTarget) {
                 try {
                     window.vm.setEditingTarget(editingTarget);
@@ -57,28 +97,293 @@
             setTimeout(function () { ignoreChanges = false; }, 500);
         }).catch(function (err) {
             console.error('[live-scratch] load error:', err);
+            reportLoad(false, err, started);
             ignoreChanges = false;
         });
     }
 
+    // Primitive inputs in SB3 block JSON ([code, value, ...]), as
+    // [opcode, field name] of the shadow block they stand for
+    var PRIMITIVES = {
+        4: ['math_number', 'NUM'],
+        5: ['math_positive_number', 'NUM'],
+        6: ['math_whole_number', 'NUM'],
+        7: ['math_integer', 'NUM'],
+        8: ['math_angle', 'NUM'],
+        9: ['colour_picker', 'COLOUR'],
+        10: ['text', 'TEXT'],
+        11: ['event_broadcast_menu', 'BROADCAST_OPTION'],
+        12: ['data_variable', 'VARIABLE'],
+        13: ['data_listcontents', 'LIST']
+    };
+    var VARIABLE_TYPES = {
+        BROADCAST_OPTION: 'broadcast_msg',
+        VARIABLE: '',
+        LIST: 'list'
+    };
+    var ID_CHARS = '!#%()*+,-./:;=?@[]^_`{|}~' +
+        'ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789';
+
+    function newBlockId() {
+        var id = '';
+        for (var i = 0; i < 20; i++) {
+            id += ID_CHARS.charAt(Math.floor(Math.random() * ID_CHARS.length));
+        }
+        return id;
+    }
+
+    // The block an SB3 input refers to: its id, or a new shadow block for a
+    // primitive (added to `blocks`)
+    function inputBlock(desc, parentId, shadow, blocks) {
+        if (!Array.isArray(desc)) return desc;
+        var info = PRIMITIVES[desc[0]];
+        if (!info) throw new Error('unknown primitive ' + desc[0]);
+        var field = { name: info[1], value: desc[1] };
+        if (desc[0] >= 11) {
+            field.id = desc[2];
+            field.variableType = VARIABLE_TYPES[info[1]];
+        }
+        var block = {
+            id: newBlockId(),
+            opcode: info[0],
+            inputs: {},
+            fields: {},
+            next: null,
+            parent: parentId,
+            shadow: shadow,
+            topLevel: false
+        };
+        block.fields[info[1]] = field;
+        // Loose variable and list reporters
+        if (desc.length > 3) {
+            block.topLevel = true;
+            block.x = desc[3];
+            block.y = desc[4];
+        }
+        blocks[block.id] = block;
+        return block.id;
+    }
+
+    // SB3 blocks in the form scratch-vm keeps them (as its sb3 loader does)
+    function deserializeBlocks(sb3Blocks) {
+        var blocks = {};
+        Object.keys(sb3Blocks).forEach(function (id) {
+            var json = sb3Blocks[id];
+            if (Array.isArray(json)) {
+                inputBlock(json, null, false, blocks);
+                return;
+            }
+            var block = Object.assign({}, json, { id: id, inputs: {}, fields: {} });
+            Object.keys(json.inputs || {}).forEach(function (name) {
+                var desc = json.inputs[name];
+                var input = { name: name, block: null, shadow: null };
+                if (desc[0] === 1) {
+                    input.block = inputBlock(desc[1], id, true, blocks);
+                    input.shadow = input.block;
+                } else {
+                    input.block = inputBlock(desc[1], id, false, blocks);
+                    if (desc[0] === 3) {
+                        input.shadow = inputBlock(desc[2], id, true, blocks);
+                    }
+                }
+                block.inputs[name] = input;
+            });
+            Object.keys(json.fields || {}).forEach(function (name) {
+                var desc = json.fields[name];
+                var field = { name: name, value: desc[0] };
+                if (desc.length > 1) field.id = desc[1];
+                if (VARIABLE_TYPES.hasOwnProperty(name)) {
+                    field.variableType = VARIABLE_TYPES[name];
+                }
+                block.fields[name] = field;
+            });
+            blocks[id] = block;
+        });
+        return blocks;
+    }
+
+    // Apply changes by id to the variables (or lists, broadcasts) of a target
+    function patchVariables(target, changes, type) {
+        if (!changes) return;
+        Object.keys(changes).forEach(function (id) {
+            var json = changes[id];
+            if (json === null) {
+                if (target.variables[id]) target.deleteVariable(id);
+                return;
+            }
+            var name = type === 'broadcast_msg' ? json : json[0];
+            var variable = target.variables[id];
+            if (!variable) {
+                target.createVariable(id, name, type, json[2] === true);
+                variable = target.variables[id];
+            } else if (variable.name !== name) {
+                target.renameVariable(id, name);
+            }
+            if (type === 'broadcast_msg') {
+                variable.value = name;
+            } else {
+                variable.value = type === 'list' ? json[1].slice() : json[1];
+            }
+        });
+    }
+
+    function patchComments(target, changes) {
+        if (!changes) return;
+        Object.keys(changes).forEach(function (id) {
+            var json = changes[id];
+            delete target.comments[id];
+            if (json === null) return;
+            target.createComment(id, json.blockId, json.text, json.x, json.y,
+                json.width, json.height, json.minimized);
+        });
+    }
+
+    function patchTarget(runtime, patch) {
+        var target = runtime.targets.find(function (t) {
+            return t.isOriginal && t.getName() === patch.name;
+        });
+        if (!target) throw new Error('no target named ' + patch.name);
+
+        patchVariables(target, patch.variables, '');
+        patchVariables(target, patch.lists, 'list');
+        patchVariables(target, patch.broadcasts, 'broadcast_msg');
+        if (patch.blocks) {
+            var blocks = deserializeBlocks(patch.blocks);
+            // Scripts being replaced can't keep running, in any clone
+            target.sprite.clones.forEach(function (clone) {
+                runtime.stopForTarget(clone);
+            });
+            target.blocks._blocks = {};
+            target.blocks._scripts = [];
+            Object.keys(blocks).forEach(function (id) {
+                target.blocks.createBlock(blocks[id]);
+            });
+            target.blocks.resetCache();
+        }
+        patchComments(target, patch.comments);
+    }
+
+    // Apply a project-patch in place, keeping everything else (running
+    // scripts of other sprites, sprite positions, ...) as it is. Falls back
+    // to loading the whole project if the patch doesn't fit the editor's.
+    function applyPatch(patch) {
+        ignoreChanges = true;
+        var started = performance.now();
+        try {
+            patch.targets.forEach(function (targetPatch) {
+                patchTarget(window.vm.runtime, targetPatch);
+            });
+            window.vm.emitWorkspaceUpdate();
+        } catch (err) {
+            console.warn('[live-scratch] patch failed, reloading project:', err);
+            reportLoad(false, err, started, patch.hash);
+            invoke('get_project_sb3').then(loadProject).catch(function (loadErr) {
+                console.error('[live-scratch] failed to reload project:', loadErr);
+                ignoreChanges = false;
+            });
+            return;
+        }
+        console.log('[live-scratch] project patched');
+        reportLoad(true, null, started, patch.hash);
+        setTimeout(function () { ignoreChanges = false; }, 500);
+    }
+
+    // Tell the backend whether scratch-vm accepted the project: the patched
+    // one with projectHash, else the SB3 fetched last
+    function reportLoad(ok, err, started, projectHash) {
+        invoke('report_editor_load', {
+            ok: ok,
+            error: err ? String(err.message || err) : null,
+            durationMs: performance.now() - started,
+            projectHash: projectHash || null
+        }).catch(function (reportErr) {
+            console.error('[live-scratch] failed to report load:', reportErr);
+        });
+    }
+
+    function saveProject() {
+        console.log('[live-scratch] saving project to backend');
+        window.vm.saveProjectSb3().then(function (blob) {
+            return blob.arrayBuffer();
+        }).then(function (buffer) {
+            // Sent as the raw request body
+            return invoke('save_project_sb3', buffer);
+        }).then(function () {
+            console.log('[live-scratch] project saved');
+        }).catch(function (err) {
+            console.error('[live-scratch] save error:', err);
+        });
+    }
+
     function setupTauri() {
-        var invoke = window.__TAURI__.core.invoke;
+        invoke = window.__TAURI__.core.invoke;
         var listen = window.__TAURI__.event.listen;
 
         // Load initial project
-        invoke('get_initial_sb3').then(function (base64) {
+        invoke('get_project_sb3').then(function (arrayBuffer) {
             console.log('[live-scratch] loading initial project');
-            var arrayBuffer = base64ToArrayBuffer(base64);
             loadProject(arrayBuffer);
         }).catch(function (err) {
             console.error('[live-scratch] failed to load initial project:', err);
+            showBuildError({ message: String(err) });
         });
 
         // Listen for file-change updates from Rust backend
         listen('sb3-updated', function (event) {
             console.log('[live-scratch] received sb3-updated event');
-            var arrayBuffer = base64ToArrayBuffer(event.payload);
-            loadProject(arrayBuffer);
+            // Unsaved editor changes: save them instead of loading over
+            // them; the backend merges both sides and sends the result
+            if (saveTimer && !ignoreChanges) {
+                clearTimeout(saveTimer);
+                saveTimer = null;
+                saveProject();
+                return;
+            }
+            // The event carries no data; fetch the SB3 as raw bytes
+            invoke('get_updated_sb3').then(loadProject).catch(function (err) {
+                console.error('[live-scratch] failed to fetch project:', err);
+            });
+        });
+
+        // Only blocks, variables or comments changed: update them in place
+        listen('project-patch', function (event) {
+            console.log('[live-scratch] received project-patch event');
+            if (saveTimer && !ignoreChanges) {
+                clearTimeout(saveTimer);
+                saveTimer = null;
+                saveProject();
+                return;
+            }
+            if (saveTimer) {
+                clearTimeout(saveTimer);
+                saveTimer = null;
+            }
+            applyPatch(event.payload);
+        });
+
+        listen('build-failed', function (event) {
+            console.error('[live-scratch] build failed:', event.payload);
+            showBuildError(event.payload);
+        });
+
+        listen('build-succeeded', function () {
+            clearBuildError();
+        });
+
+        listen('sync-conflict', function (event) {
+            console.warn('[live-scratch] sync conflict:', event.payload);
+            showConflict(event.payload);
+        });
+
+        // Another project of the workspace was opened; drop any pending save
+        // so edits to the old project don't land in the new one
+        listen('project-switched', function (event) {
+            console.log('[live-scratch] switched to project', event.payload.name);
+            if (saveTimer) {
+                clearTimeout(saveTimer);
+                saveTimer = null;
+            }
+            clearBuildError();
         });
 
         // Listen for project changes made in the Scratch editor
@@ -89,17 +394,7 @@
                 saveTimer = null;
                 if (ignoreChanges) return;
 
-                console.log('[live-scratch] saving project to backend');
-                window.vm.saveProjectSb3().then(function (blob) {
-                    return blob.arrayBuffer();
-                }).then(function (buffer) {
-                    var base64 = arrayBufferToBase64(buffer);
-                    return invoke('save_project_from_editor', { sb3Base64: base64 });
-                }).then(function () {
-                    console.log('[live-scratch] project saved');
-                }).catch(function (err) {
-                    console.error('[live-scratch] save error:', err);
-                });
+                saveProject();
             }, SAVE_DEBOUNCE);
         });
     }
@@ -0,0 +1,87 @@
+// Stands in for window.__TAURI__ when the editor is opened in a browser
+// through the app's local server, so live-reload.js works unchanged:
+// invoke() posts to /api/<command> and listen() subscribes to the events
+// WebSocket. Binary arguments and results (SB3s) travel as raw bytes.
+(function () {
+    'use strict';
+
+    if (window.__TAURI__) return;
+
+    var RECONNECT_DELAY = 1000;
+    var listeners = {};
+    // Given by the server on connect; sent with saves so they aren't
+    // echoed back to this browser
+    var clientId = null;
+    var wasConnected = false;
+
+    function invoke(command, args) {
+        var binary = args instanceof ArrayBuffer || ArrayBuffer.isView(args);
+        var headers = {
+            'Content-Type': binary ? 'application/octet-stream' : 'application/json'
+        };
+        if (clientId !== null) {
+            headers['X-Live-Scratch-Client'] = String(clientId);
+        }
+        return fetch('/api/' + command, {
+            method: 'POST',
+            headers: headers,
+            body: binary ? args : JSON.stringify(args || {})
+        }).then(function (response) {
+            var type = response.headers.get('Content-Type') || '';
+            if (response.ok && type.indexOf('application/octet-stream') === 0) {
+                return response.arrayBuffer();
+            }
+            return response.json().then(function (result) {
+                if (!response.ok) throw result;
+                return result;
+            });
+        });
+    }
+
+    function dispatch(event, payload) {
+        (listeners[event] || []).forEach(function (handler) {
+            handler({ event: event, payload: payload });
+        });
+    }
+
+    function listen(event, handler) {
+        (listeners[event] = listeners[event] || []).push(handler);
+        return Promise.resolve(function () {
+            listeners[event] = listeners[event].filter(function (h) {
+                return h !== handler;
+            });
+        });
+    }
+
+    function connect() {
+        var protocol = location.protocol === 'https:' ? 'wss:' : 'ws:';
+        var socket = new WebSocket(protocol + '//' + location.host + '/events');
+        socket.onmessage = function (message) {
+            var data = JSON.parse(message.data);
+            if (data.event === 'live-scratch-client') {
+                clientId = data.payload;
+                // Catch up on updates missed while disconnected: have the
+                // current project sent, then load it like any update
+                if (wasConnected) {
+                    invoke('get_project_sb3').then(function () {
+                        dispatch('sb3-updated', null);
+                    });
+                }
+                wasConnected = true;
+                return;
+            }
+            dispatch(data.event, data.payload);
+        };
+        socket.onclose = function () {
+            console.warn('[live-scratch] disconnected from the app, retrying');
+            clientId = null;
+            setTimeout(connect, RECONNECT_DELAY);
+        };
+    }
+
+    window.__TAURI__ = {
+        core: { invoke: invoke },
+        event: { listen: listen }
+    };
+    connect();
+})();
@@ -1,3 +1,5 @@
 fn main() {
+    // Only the desktop app is a Tauri application
+    #[cfg(feature = "app")]
     tauri_build::build()
 }
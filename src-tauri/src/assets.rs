@@ -0,0 +1,553 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::ignore_rules::{self, IgnoreRules, IGNORE_FILE};
+use crate::journal::Transaction;
+use crate::layout;
+
+/// File formats (dataFormat) of costumes and sounds
+const ASSET_FORMATS: &[&str] = &["svg", "png", "jpg", "jpeg", "bmp", "gif", "wav", "mp3"];
+
+/// A costume or sound referenced from project.json.
+pub struct AssetRef {
+    /// Index into the project's "targets" array
+    pub target: usize,
+    /// "costumes" or "sounds"
+    pub list: &'static str,
+    /// Index into the target's costume or sound list
+    pub index: usize,
+    /// Hash-based file name inside the SB3, e.g. "83a9...67.wav"
+    pub md5ext: String,
+    /// Human-readable workspace path, e.g. "Sprite1/costume1.svg"
+    pub named_path: PathBuf,
+}
+
+/// MD5 hex digest, as used by Scratch for assetId.
+pub fn md5_hex(data: &[u8]) -> String {
+    format!("{:x}", md5::compute(data))
+}
+
+/// List every costume and sound in the project with both its hash-based
+/// name and its human-readable workspace path (<target>/<asset name>.<ext>).
+/// Colliding names get a " (2)" suffix, in costume-then-sound order, so
+/// extract and build always agree on the same paths. Target folders never
+/// take the name of a project file or of a folder the default ignore rules
+/// skip (e.g. a sprite named "targets" uses "targets (2)").
+pub fn asset_refs(project: &Value) -> Vec<AssetRef> {
+    let mut refs = Vec::new();
+    let Some(targets) = project.get("targets").and_then(Value::as_array) else {
+        return refs;
+    };
+
+    let mut taken_dirs: HashSet<String> =
+        [layout::PROJECT_JSON, layout::META_JSON, layout::TARGETS_DIR]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    for (t, target) in targets.iter().enumerate() {
+        let target_name = target.get("name").and_then(Value::as_str).unwrap_or("target");
+        let base = layout::sanitize_file_name(target_name);
+        let mut dir = layout::unique_file_name(&base, "", &mut taken_dirs);
+        while ignore_rules::is_ignored_by_default(&dir) {
+            dir = layout::unique_file_name(&base, "", &mut taken_dirs);
+        }
+
+        let mut taken_files = HashSet::new();
+        for list in ["costumes", "sounds"] {
+            let Some(items) = target.get(list).and_then(Value::as_array) else {
+                continue;
+            };
+            for (i, asset) in items.iter().enumerate() {
+                let Some(md5ext) = asset_md5ext(asset) else {
+                    continue;
+                };
+                let ext = asset
+                    .get("dataFormat")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let name = asset.get("name").and_then(Value::as_str).unwrap_or(list);
+                let file_name = layout::unique_file_name(
+                    &layout::sanitize_file_name(name),
+                    ext,
+                    &mut taken_files,
+                );
+                refs.push(AssetRef {
+                    target: t,
+                    list,
+                    index: i,
+                    md5ext,
+                    named_path: Path::new(&dir).join(file_name),
+                });
+            }
+        }
+    }
+    refs
+}
+
+/// Read the costumes and sounds stored under their human-readable names,
+/// recompute their MD5 and rewrite assetId/md5ext in the project to match.
+/// Returns (md5ext, content) for each asset found; assets without a named
+/// file are left as they are.
+pub fn resolve_named_assets(workspace: &Path, project: &mut Value) -> Vec<(String, Vec<u8>)> {
+    let mut found = Vec::new();
+    for asset_ref in asset_refs(project) {
+        let path = workspace.join(&asset_ref.named_path);
+        if !path.is_file() {
+            continue;
+        }
+        let content = match fs::read(&path) {
+            Ok(c) => c,
+            Err(err) => {
+                log::error!("Failed to read {:?}: {}", path, err);
+                continue;
+            }
+        };
+
+        let asset_id = md5_hex(&content);
+        let asset = &mut project["targets"][asset_ref.target][asset_ref.list][asset_ref.index];
+        let ext = asset
+            .get("dataFormat")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let md5ext = format!("{}.{}", asset_id, ext);
+        asset["assetId"] = Value::String(asset_id);
+        asset["md5ext"] = Value::String(md5ext.clone());
+        found.push((md5ext, content));
+    }
+    found
+}
+
+/// Costumes and sounds whose file is missing from both the workspace root
+/// (<md5ext>) and its human-readable path, as (JSON pointer, message) pairs
+/// like validate::validate_project's. An md5ext that isn't a plain file
+/// name is reported too, and so is a file matching the ignore rules, which
+/// builds must not read.
+pub fn missing_assets(workspace: &Path, project: &Value) -> Vec<(String, String)> {
+    let ignore = IgnoreRules::load(workspace);
+    let mut missing = Vec::new();
+    for asset_ref in asset_refs(project) {
+        let pointer = format!(
+            "/targets/{}/{}/{}",
+            asset_ref.target, asset_ref.list, asset_ref.index
+        );
+        if !is_file_name(&asset_ref.md5ext) {
+            let message = format!("invalid asset file name {:?}", asset_ref.md5ext);
+            missing.push((pointer, message));
+            continue;
+        }
+        let named_path = asset_ref.named_path.to_string_lossy().replace('\\', "/");
+        let found = [Path::new(&named_path), Path::new(&asset_ref.md5ext)]
+            .into_iter()
+            .find(|path| workspace.join(path).is_file());
+        if let Some(path) = found {
+            if ignore.is_ignored(path) {
+                let message = format!(
+                    "asset file {} is ignored by {}",
+                    path.display(),
+                    IGNORE_FILE
+                );
+                missing.push((pointer, message));
+            }
+            continue;
+        }
+        missing.push((
+            pointer,
+            format!(
+                "asset file not found: {} (or {})",
+                named_path, asset_ref.md5ext
+            ),
+        ));
+    }
+    missing
+}
+
+/// Workspace files a build leaves out: top-level files other than the
+/// project files and the assets `project` references, and files in the
+/// asset folders that aren't one of its costumes or sounds. Ignored files
+/// (see ignore_rules) aren't listed. `project` must have its named assets
+/// resolved (see resolve_named_assets). Paths are workspace-relative, with
+/// `/`.
+pub fn unreferenced_files(workspace: &Path, project: &Value) -> Vec<String> {
+    let ignore = IgnoreRules::load(workspace);
+    let refs = asset_refs(project);
+    let mut used = referenced_paths(workspace, project);
+    used.insert(PathBuf::from(layout::PROJECT_JSON));
+    used.insert(PathBuf::from(layout::META_JSON));
+    used.insert(PathBuf::from(IGNORE_FILE));
+    let asset_dirs: HashSet<PathBuf> = refs
+        .iter()
+        .filter_map(|r| r.named_path.parent().map(Path::to_path_buf))
+        .collect();
+
+    let mut unreferenced = Vec::new();
+    for dir in std::iter::once(PathBuf::new()).chain(asset_dirs) {
+        let Ok(entries) = fs::read_dir(workspace.join(&dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let relative = dir.join(entry.file_name());
+            if !entry.path().is_file() || used.contains(&relative) || ignore.is_ignored(&relative) {
+                continue;
+            }
+            unreferenced.push(relative.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    unreferenced.sort();
+    unreferenced
+}
+
+/// Costume and sound files in the workspace that `project` doesn't use:
+/// hash-named files (<md5>.<ext>) in the root, files with an asset
+/// extension in the folders of the project's targets, and every file of a
+/// top-level folder holding nothing but costumes and sounds (the folder of
+/// a deleted sprite). Other files, such as images in a docs folder, are
+/// never listed, and neither are ignored files. `project` must have its
+/// named assets resolved (see resolve_named_assets).
+pub fn orphaned_assets(workspace: &Path, project: &Value) -> Vec<PathBuf> {
+    let ignore = IgnoreRules::load(workspace);
+    let used = referenced_paths(workspace, project);
+    let asset_dirs: HashSet<PathBuf> = asset_refs(project)
+        .iter()
+        .filter_map(|r| r.named_path.parent().map(Path::to_path_buf))
+        .collect();
+    let unused = |relative: &PathBuf| {
+        is_asset_file(relative) && !used.contains(relative) && !ignore.is_ignored(relative)
+    };
+
+    let mut orphaned = Vec::new();
+    let Ok(entries) = fs::read_dir(workspace) else {
+        return orphaned;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let relative = PathBuf::from(&name);
+        let path = entry.path();
+        if path.is_file() {
+            let hash_named = name.split_once('.').is_some_and(|(stem, _)| is_md5(stem));
+            if hash_named && unused(&relative) {
+                orphaned.push(relative);
+            }
+            continue;
+        }
+        if !path.is_dir()
+            || name.starts_with('.')
+            || name == layout::TARGETS_DIR
+            || layout::has_project(&path)
+            || ignore.is_ignored(&relative)
+        {
+            continue;
+        }
+        let (files, has_subfolders) = folder_files(&path, &relative, &ignore);
+        if asset_dirs.contains(&relative) {
+            orphaned.extend(files.into_iter().filter(|file| unused(file)));
+        } else if !has_subfolders
+            && !files.is_empty()
+            && files.iter().all(|file| is_asset_file(file))
+        {
+            orphaned.extend(files);
+        }
+    }
+    orphaned.sort();
+    orphaned
+}
+
+/// The files directly in the top-level folder `dir` (`relative` to the
+/// workspace) that aren't ignored, and whether it has other entries
+/// (subfolders) that aren't.
+fn folder_files(dir: &Path, relative: &Path, ignore: &IgnoreRules) -> (Vec<PathBuf>, bool) {
+    let mut files = Vec::new();
+    let mut has_subfolders = false;
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (files, has_subfolders);
+    };
+    for entry in entries.flatten() {
+        let file = relative.join(entry.file_name());
+        if ignore.is_ignored(&file) {
+            continue;
+        }
+        if entry.path().is_file() {
+            files.push(file);
+        } else {
+            has_subfolders = true;
+        }
+    }
+    (files, has_subfolders)
+}
+
+/// The costume and sound files `old` was built from (its human-readable
+/// file, or <md5ext> in the root when that is missing) that `new` doesn't
+/// use, e.g. after costumes were deleted or renamed in the editor.
+pub fn dropped_assets(workspace: &Path, old: &Value, new: &Value) -> Vec<PathBuf> {
+    let ignore = IgnoreRules::load(workspace);
+    let kept = referenced_paths(workspace, new);
+    let mut dropped: Vec<PathBuf> = asset_refs(old)
+        .into_iter()
+        .filter(|r| is_file_name(&r.md5ext))
+        .filter_map(|r| {
+            [r.named_path, PathBuf::from(r.md5ext)]
+                .into_iter()
+                .find(|path| workspace.join(path).is_file())
+        })
+        .filter(|path| !kept.contains(path) && !ignore.is_ignored(path))
+        .collect();
+    dropped.sort();
+    dropped.dedup();
+    dropped
+}
+
+/// The workspace paths the costumes and sounds of `project` are read
+/// from: their human-readable path, and <md5ext> in the root only while
+/// that is missing (a hash-named copy next to a named file is unused).
+fn referenced_paths(workspace: &Path, project: &Value) -> HashSet<PathBuf> {
+    let mut paths = HashSet::new();
+    for asset_ref in asset_refs(project) {
+        if !workspace.join(&asset_ref.named_path).is_file() {
+            paths.insert(PathBuf::from(asset_ref.md5ext));
+        }
+        paths.insert(asset_ref.named_path);
+    }
+    paths
+}
+
+/// Whether `path` has the extension of a costume or sound format.
+fn is_asset_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ASSET_FORMATS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Whether `name` is a single, ordinary file name (no separators or `..`).
+fn is_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(std::path::Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['\\', ':'])
+}
+
+/// Find hash-named asset files (e.g. "bcf4...1dbc.svg") whose content no
+/// longer matches their name because they were edited in place, rename them
+/// to their new MD5 and update every costume/sound that references them.
+/// Assets stored under their human-readable name are rehashed at build time
+/// instead and are skipped here. Returns the (old, new) md5ext pairs.
+pub fn rehash_edited_assets(workspace: &Path) -> Result<Vec<(String, String)>, String> {
+    let mut project = layout::read_project(workspace)?;
+
+    let refs = asset_refs(&project);
+    let mut renamed: Vec<(String, String)> = Vec::new();
+    // (file, its new path or None if the new path already holds it)
+    let mut moves: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+    for asset_ref in &refs {
+        if renamed.iter().any(|(old, _)| *old == asset_ref.md5ext)
+            || workspace.join(&asset_ref.named_path).is_file()
+        {
+            continue;
+        }
+        let Some((old_id, ext)) = asset_ref.md5ext.split_once('.') else {
+            continue;
+        };
+        if !is_md5(old_id) {
+            continue;
+        }
+
+        let old_path = workspace.join(&asset_ref.md5ext);
+        let Ok(content) = fs::read(&old_path) else {
+            continue;
+        };
+        let new_id = md5_hex(&content);
+        if new_id == old_id {
+            continue;
+        }
+
+        let new_md5ext = format!("{}.{}", new_id, ext);
+        let new_path = workspace.join(&new_md5ext);
+        // Same content may already be stored under the new name
+        let destination = (!new_path.exists()).then_some(new_path);
+        moves.push((old_path, destination));
+        renamed.push((asset_ref.md5ext.clone(), new_md5ext));
+    }
+
+    if renamed.is_empty() {
+        return Ok(renamed);
+    }
+
+    // Rename the files and update the project in one journaled update
+    let mut tx = Transaction::begin(workspace)?;
+    for (old_path, destination) in &moves {
+        match destination {
+            Some(new_path) => tx.move_to(old_path, new_path)?,
+            None => tx.remove(old_path)?,
+        }
+    }
+
+    for (old, new) in &renamed {
+        let (new_id, _) = new.split_once('.').unwrap_or((new.as_str(), ""));
+        let mut count = 0;
+        for asset_ref in refs.iter().filter(|r| r.md5ext == *old) {
+            let asset = &mut project["targets"][asset_ref.target][asset_ref.list][asset_ref.index];
+            asset["assetId"] = Value::String(new_id.to_string());
+            asset["md5ext"] = Value::String(new.clone());
+            count += 1;
+        }
+        log::info!(
+            "[live-scratch] {} was edited in place; renamed to {} and updated {} reference(s)",
+            old,
+            new,
+            count
+        );
+    }
+
+    layout::write_project(&mut tx, &project)?;
+    tx.commit()?;
+    Ok(renamed)
+}
+
+fn is_md5(s: &str) -> bool {
+    s.len() == 32 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// md5ext of a costume or sound, falling back to assetId + dataFormat for
+/// projects that omit it.
+fn asset_md5ext(asset: &Value) -> Option<String> {
+    if let Some(md5ext) = asset.get("md5ext").and_then(Value::as_str) {
+        return Some(md5ext.to_string());
+    }
+    let asset_id = asset.get("assetId").and_then(Value::as_str)?;
+    let ext = asset.get("dataFormat").and_then(Value::as_str)?;
+    Some(format!("{}.{}", asset_id, ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef";
+
+    fn costume(name: &str) -> Value {
+        json!({
+            "name": name,
+            "assetId": HASH,
+            "md5ext": format!("{}.svg", HASH),
+            "dataFormat": "svg"
+        })
+    }
+
+    fn target(name: &str) -> Value {
+        json!({ "name": name, "costumes": [costume("costume1")], "sounds": [] })
+    }
+
+    fn asset_dirs(names: &[&str]) -> Vec<String> {
+        let project =
+            json!({ "targets": names.iter().map(|name| target(name)).collect::<Vec<_>>() });
+        asset_refs(&project)
+            .iter()
+            .map(|r| r.named_path.parent().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    /// A fresh workspace folder under the system temp dir holding `files`.
+    fn temp_workspace(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "live-scratch-assets-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn asset_folders_use_target_names() {
+        assert_eq!(
+            asset_dirs(&["Stage", "Sprite1", "sprite1", "a/b"]),
+            ["Stage", "Sprite1", "sprite1 (2)", "a_b"]
+        );
+    }
+
+    #[test]
+    fn asset_folders_avoid_reserved_and_ignored_names() {
+        let names = [
+            "targets",
+            "project.json",
+            "node_modules",
+            ".git",
+            ".live-scratch",
+            ".vscode",
+            "notes.md",
+        ];
+        assert_eq!(
+            asset_dirs(&names),
+            [
+                "targets (2)",
+                "project.json (2)",
+                "node_modules (2)",
+                "_git",
+                "_live-scratch",
+                "_vscode",
+                "notes.md (2)"
+            ]
+        );
+    }
+
+    #[test]
+    fn orphaned_assets_only_lists_unused_costumes_and_sounds() {
+        let used_hash = format!("{}.svg", HASH);
+        let unused_hash = format!("{}.svg", "f".repeat(32));
+        let ws = temp_workspace(
+            "orphaned",
+            &[
+                "project.json",
+                "README.md",
+                "logo.png",
+                &used_hash,
+                &unused_hash,
+                "Stage/costume1.svg",
+                "Sprite1/costume1.svg",
+                "Sprite1/old.svg",
+                "Sprite1/notes.txt",
+                "Sprite2/costume1.svg",
+                "Sprite2/pop.wav",
+                "docs/diagram.png",
+                "docs/notes.txt",
+                "art/reference.png",
+                "art/sketches/cat.png",
+                "node_modules/icon.png",
+                ".cache/thumb.png",
+                "Other/project.json",
+                "Other/cat.png",
+            ],
+        );
+        let project = json!({ "targets": [target("Stage"), target("Sprite1")] });
+
+        let mut expected = vec![
+            // A hash-named copy next to its named files, and an unused one
+            PathBuf::from(used_hash),
+            PathBuf::from(unused_hash),
+            Path::new("Sprite1").join("old.svg"),
+            // The folder of a deleted sprite
+            Path::new("Sprite2").join("costume1.svg"),
+            Path::new("Sprite2").join("pop.wav"),
+        ];
+        expected.sort();
+        assert_eq!(orphaned_assets(&ws, &project), expected);
+        fs::remove_dir_all(&ws).unwrap();
+    }
+
+    #[test]
+    fn orphaned_assets_keeps_hash_named_files_in_use() {
+        let ws = temp_workspace("hash-named", &["project.json", &format!("{}.svg", HASH)]);
+        let project = json!({ "targets": [target("Stage")] });
+
+        assert!(orphaned_assets(&ws, &project).is_empty());
+        fs::remove_dir_all(&ws).unwrap();
+    }
+}
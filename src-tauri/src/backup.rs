@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ignore_rules::IgnoreRules;
+use crate::journal::Transaction;
+use crate::layout;
+use crate::status;
+
+/// Folder under .live-scratch holding the archived workspace contents
+const BACKUP_DIR: &str = "backups";
+/// Oldest backups of each reason beyond this many are deleted
+const MAX_BACKUPS: usize = 20;
+
+pub fn backups_dir(workspace: &Path) -> PathBuf {
+    workspace.join(status::STATE_DIR).join(BACKUP_DIR)
+}
+
+/// Stage moving the project files of the workspace into
+/// .live-scratch/backups/<timestamp>-<reason>/, leaving it empty apart from
+/// files that aren't part of the project (hidden files such as .git or
+/// .live-scratch, ignored files like CLAUDE.md, and nested projects).
+/// Restoring is a matter of moving the files back.
+/// Returns the backup folder, or None if there was nothing to archive.
+pub fn archive_workspace(tx: &mut Transaction, reason: &str) -> Result<Option<PathBuf>, String> {
+    let workspace = tx.workspace().to_path_buf();
+    let entries = fs::read_dir(&workspace)
+        .map_err(|e| format!("Failed to read {:?}: {}", workspace, e))?;
+    let ignore = IgnoreRules::load(&workspace);
+    let to_archive: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|path| is_project_file(path) && !ignore.is_ignored(path))
+        .collect();
+    if to_archive.is_empty() {
+        return Ok(None);
+    }
+
+    let backup = backups_dir(&workspace).join(format!("{}-{}", status::now_ms(), reason));
+    for path in &to_archive {
+        let Some(name) = path.file_name() else { continue };
+        tx.move_to(path, &backup.join(name))?;
+    }
+    log::info!(
+        "[live-scratch] archiving {} workspace entries to {:?}",
+        to_archive.len(),
+        backup
+    );
+    Ok(Some(backup))
+}
+
+/// Stage moving `files` (relative to the workspace) into
+/// .live-scratch/backups/<timestamp>-<reason>/, under the same relative
+/// paths. Returns the backup folder, or None if there was nothing to move.
+pub fn archive_files(
+    tx: &mut Transaction,
+    files: &[PathBuf],
+    reason: &str,
+) -> Result<Option<PathBuf>, String> {
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let workspace = tx.workspace().to_path_buf();
+    let backup = backups_dir(&workspace).join(format!("{}-{}", status::now_ms(), reason));
+    for file in files {
+        tx.move_to(&workspace.join(file), &backup.join(file))?;
+    }
+    log::info!("[live-scratch] moving {} files to {:?}", files.len(), backup);
+    Ok(Some(backup))
+}
+
+fn is_project_file(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if name.starts_with('.') {
+        return false;
+    }
+    !(path.is_dir() && layout::has_project(path))
+}
+
+/// Delete the oldest backups made for `reason` beyond MAX_BACKUPS. Each
+/// reason has its own limit, so frequent small backups (e.g. unused assets
+/// moved away on every save) never push out the archive of a whole
+/// workspace made when an SB3 was opened.
+pub fn prune(workspace: &Path, reason: &str) {
+    let Ok(entries) = fs::read_dir(backups_dir(workspace)) else {
+        return;
+    };
+    let mut backups: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && backup_reason(p) == Some(reason))
+        .collect();
+    if backups.len() <= MAX_BACKUPS {
+        return;
+    }
+    // Names start with a millisecond timestamp, so they sort by age
+    backups.sort();
+    for old in &backups[..backups.len() - MAX_BACKUPS] {
+        if let Err(err) = fs::remove_dir_all(old) {
+            log::warn!("[live-scratch] failed to remove old backup {:?}: {}", old, err);
+        }
+    }
+}
+
+/// The reason in a backup folder's name (<timestamp>-<reason>).
+fn backup_reason(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    name.split_once('-').map(|(_, reason)| reason)
+}
@@ -0,0 +1,303 @@
+//! Headless access to the workspace logic, for CI jobs, scripts and agents
+//! working without the GUI. Builds and extracts exactly like the app does.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use live_scratch::ignore_rules::IgnoreRules;
+use live_scratch::{assets, echo, export, journal, layout, workspace};
+use notify::{EventKind, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult};
+
+const USAGE: &str = "\
+Usage:
+  live-scratch-cli pack <dir> [-o <out.sb3>] [-l <level>]   Build the project in <dir> into an SB3
+  live-scratch-cli unpack <in.sb3> <dir>                    Replace the project in <dir> with an SB3
+  live-scratch-cli validate <dir>                           Check the project in <dir> without building
+  live-scratch-cli watch <dir> [-o <out.sb3>] [-l <level>]  Pack, then pack again on every change
+  live-scratch-cli clean <dir>                              Move costume and sound files the project no longer uses to backups
+
+The output defaults to <dir>.sb3 next to <dir>. Entries are deflated at
+<level>, from 0 (stored) to 9 (smallest), 6 by default.";
+
+enum Command {
+    Pack {
+        dir: PathBuf,
+        output: Option<PathBuf>,
+        level: u32,
+    },
+    Unpack {
+        input: PathBuf,
+        dir: PathBuf,
+    },
+    Validate {
+        dir: PathBuf,
+    },
+    Watch {
+        dir: PathBuf,
+        output: Option<PathBuf>,
+        level: u32,
+    },
+    Clean {
+        dir: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    // Errors are reported on stderr below; RUST_LOG shows the app's log
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("off")).init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if matches!(
+        args.first().map(String::as_str),
+        Some("help" | "-h" | "--help")
+    ) {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let Some(command) = parse_args(&args) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    let result = match command {
+        Command::Pack { dir, output, level } => pack(&dir, output, level),
+        Command::Unpack { input, dir } => unpack(&input, &dir),
+        Command::Validate { dir } => validate(&dir),
+        Command::Watch { dir, output, level } => watch(&dir, output, level),
+        Command::Clean { dir } => clean(&dir),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Option<Command> {
+    let (command, rest) = args.split_first()?;
+    let mut positional = Vec::new();
+    let mut output = None;
+    let mut level = None;
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(rest.next()?)),
+            "-l" | "--level" => level = Some(parse_level(rest.next()?)?),
+            _ if arg.starts_with('-') => return None,
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let packs = output.is_some() || level.is_some();
+    let level = level.unwrap_or(export::DEFAULT_LEVEL);
+    match (command.as_str(), positional.as_slice()) {
+        ("pack", [dir]) => Some(Command::Pack {
+            dir: dir.clone(),
+            output,
+            level,
+        }),
+        ("watch", [dir]) => Some(Command::Watch {
+            dir: dir.clone(),
+            output,
+            level,
+        }),
+        ("unpack", [input, dir]) if !packs => Some(Command::Unpack {
+            input: input.clone(),
+            dir: dir.clone(),
+        }),
+        ("validate", [dir]) if !packs => Some(Command::Validate { dir: dir.clone() }),
+        ("clean", [dir]) if !packs => Some(Command::Clean { dir: dir.clone() }),
+        _ => None,
+    }
+}
+
+fn parse_level(arg: &str) -> Option<u32> {
+    arg.parse().ok().filter(|level| *level <= export::MAX_LEVEL)
+}
+
+fn pack(dir: &Path, output: Option<PathBuf>, level: u32) -> Result<(), String> {
+    let dir = open_project(dir)?;
+    let output = output_path(&dir, output)?;
+    let data = build(&dir, level)?;
+    write_output(&output, &data)?;
+    report(&output, &data);
+    Ok(())
+}
+
+fn unpack(input: &Path, dir: &Path) -> Result<(), String> {
+    let data = fs::read(input).map_err(|e| format!("Failed to read {:?}: {}", input, e))?;
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    let backup = workspace::open_sb3(dir, &data)?;
+    println!("Unpacked {} into {}", input.display(), dir.display());
+    if let Some(backup) = backup {
+        println!("Previous project files moved to {}", backup.display());
+    }
+    Ok(())
+}
+
+fn validate(dir: &Path) -> Result<(), String> {
+    let dir = open_project(dir)?;
+    let diagnostics = workspace::validate_workspace(&dir);
+    if diagnostics.is_empty() {
+        warn_unreferenced(&dir);
+        println!("{}: OK", dir.display());
+        return Ok(());
+    }
+    for diag in &diagnostics {
+        println!("{}", diag);
+    }
+    Err(format!("{} problem(s) found", diagnostics.len()))
+}
+
+fn clean(dir: &Path) -> Result<(), String> {
+    let dir = open_project(dir)?;
+    let moved = workspace::clean_unused_assets(&dir)?;
+    for file in &moved {
+        println!("Moved {}", file);
+    }
+    println!(
+        "{} unused asset file(s) moved to .live-scratch/backups/",
+        moved.len()
+    );
+    Ok(())
+}
+
+/// Pack on start and after every change, until interrupted. A failed build
+/// is reported and leaves the previous output in place.
+fn watch(dir: &Path, output: Option<PathBuf>, level: u32) -> Result<(), String> {
+    let dir = open_project(dir)?;
+    let output = output_path(&dir, output)?;
+    let tmp = tmp_path(&output);
+
+    let (tx, rx) = mpsc::channel::<DebounceEventResult>();
+    let mut debouncer = new_debouncer(Duration::from_millis(300), None, tx)
+        .map_err(|e| format!("Failed to create file watcher: {}", e))?;
+    debouncer
+        .watch(&dir, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch {:?}: {}", dir, e))?;
+
+    rebuild(&dir, &output, level);
+    println!("Watching {} (Ctrl+C to stop)", dir.display());
+
+    for result in rx {
+        let events = match result {
+            Ok(events) => events,
+            Err(errors) => {
+                for err in errors {
+                    log::error!("Watch error: {}", err);
+                }
+                continue;
+            }
+        };
+        // Same filter as the app, plus our own output when it lives in the
+        // project folder
+        let ignore = IgnoreRules::load(&dir);
+        let changed = events.iter().any(|e| {
+            !matches!(e.kind, EventKind::Access(_))
+                && e.paths.iter().any(|p| {
+                    *p != output
+                        && *p != tmp
+                        && layout::is_project_change(&dir, &ignore, p)
+                        && !echo::is_echo(p)
+                })
+        });
+        if changed {
+            rebuild(&dir, &output, level);
+        }
+    }
+    Ok(())
+}
+
+fn rebuild(dir: &Path, output: &Path, level: u32) {
+    // Hash-named assets edited in place get renamed to their new MD5, as in
+    // the app. A broken project.json is reported by the build below.
+    let _ = assets::rehash_edited_assets(dir);
+    match build(dir, level).and_then(|data| write_output(output, &data).map(|_| data)) {
+        Ok(data) => report(output, &data),
+        Err(err) => eprintln!("error: {}", err),
+    }
+}
+
+fn build(dir: &Path, level: u32) -> Result<Vec<u8>, String> {
+    let data = workspace::build_sb3_compressed(dir, level).map_err(|err| {
+        for diag in &err.diagnostics {
+            eprintln!("{}", diag);
+        }
+        format!("Build failed: {}", err.message)
+    })?;
+    warn_unreferenced(dir);
+    Ok(data)
+}
+
+/// Warn about workspace files the SB3 leaves out (see
+/// assets::unreferenced_files).
+fn warn_unreferenced(dir: &Path) {
+    let Ok(project) = workspace::current_project(dir) else {
+        return;
+    };
+    for file in assets::unreferenced_files(dir, &project) {
+        eprintln!(
+            "warning: {} is not referenced by project.json; left out",
+            file
+        );
+    }
+}
+
+/// Print the size of the SB3 written to `output`, with a warning for each
+/// file inside over the Scratch website's limits.
+fn report(output: &Path, data: &[u8]) {
+    println!("Wrote {} ({} bytes)", output.display(), data.len());
+    match export::size_report(data) {
+        Ok(report) => {
+            for problem in &report.over_limits {
+                eprintln!("warning: too large for the Scratch website: {}", problem);
+            }
+        }
+        Err(err) => eprintln!("error: {}", err),
+    }
+}
+
+/// Canonical path of a project folder, after finishing any update the app
+/// was interrupted in (see journal::recover).
+fn open_project(dir: &Path) -> Result<PathBuf, String> {
+    let dir = fs::canonicalize(dir).map_err(|e| format!("Failed to open {:?}: {}", dir, e))?;
+    if !layout::has_project(&dir) {
+        return Err(format!("No project found in {:?}", dir));
+    }
+    journal::recover(&dir);
+    Ok(dir)
+}
+
+/// Absolute output path: the given one, or <dir>.sb3 next to `dir`.
+fn output_path(dir: &Path, output: Option<PathBuf>) -> Result<PathBuf, String> {
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let name = dir
+                .file_name()
+                .ok_or_else(|| format!("Use -o to name the output for {:?}", dir))?;
+            dir.with_file_name(format!("{}.sb3", name.to_string_lossy()))
+        }
+    };
+    std::path::absolute(&output).map_err(|e| format!("Invalid output path {:?}: {}", output, e))
+}
+
+/// Write the SB3 via a temporary file, so readers never see a partial one.
+fn write_output(output: &Path, data: &[u8]) -> Result<(), String> {
+    let tmp = tmp_path(output);
+    fs::write(&tmp, data)
+        .and_then(|_| fs::rename(&tmp, output))
+        .map_err(|e| format!("Failed to write {:?}: {}", output, e))
+}
+
+fn tmp_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
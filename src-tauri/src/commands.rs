@@ -1,50 +1,278 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
 
 use base64::Engine;
+use tauri::ipc::{InvokeBody, Request, Response};
 use tauri::State;
 
-use crate::watcher;
+use crate::export::{self, SizeReport};
+use crate::history::{self, Snapshot};
+use crate::layout;
+use crate::projects::{self, ProjectInfo};
+use crate::settings;
+use crate::status;
+use crate::sync::{self, Editor, JobKind};
+use crate::validate::Diagnostic;
 use crate::workspace;
 
-pub struct WorkspacePath(pub PathBuf);
+/// The workspace root and the project currently open in it.
+pub struct WorkspacePath {
+    pub root: PathBuf,
+    /// Folder containing default-project/, used to seed new projects
+    pub resource_dir: PathBuf,
+    current: Mutex<(String, PathBuf)>,
+}
+
+impl WorkspacePath {
+    pub fn new(root: PathBuf, resource_dir: PathBuf, name: String, dir: PathBuf) -> Self {
+        WorkspacePath {
+            root,
+            resource_dir,
+            current: Mutex::new((name, dir)),
+        }
+    }
+
+    /// Directory of the current project
+    pub fn get(&self) -> PathBuf {
+        self.current.lock().unwrap().1.clone()
+    }
+
+    /// Name of the current project (see projects::list_projects)
+    pub fn project_name(&self) -> String {
+        self.current.lock().unwrap().0.clone()
+    }
+
+    pub fn set(&self, name: String, dir: PathBuf) {
+        *self.current.lock().unwrap() = (name, dir);
+    }
+}
+
+/// Error for a job that never ran (only EditorSave and Rebuild jobs can
+/// be superseded)
+const WORKER_STOPPED: &str = "The sync worker has stopped";
+
+/// Wait for a job queued on the sync worker without blocking the async
+/// runtime. None if a newer job of the same kind superseded it.
+async fn wait_for<T: Send + 'static>(receiver: mpsc::Receiver<T>) -> Option<T> {
+    tauri::async_runtime::spawn_blocking(move || receiver.recv().ok())
+        .await
+        .ok()
+        .flatten()
+}
+
+/// The workspace project as an SB3, sent as raw bytes. Counts as sent to
+/// the editor (see report_editor_load).
+#[tauri::command]
+pub async fn get_project_sb3(state: State<'_, WorkspacePath>) -> Result<Response, String> {
+    project_sb3(state.get(), Editor::Webview)
+        .await
+        .map(Response::new)
+}
+
+/// get_project_sb3 as base64, for clients without binary IPC.
+#[tauri::command]
+pub async fn get_initial_sb3(state: State<'_, WorkspacePath>) -> Result<String, String> {
+    let sb3 = project_sb3(state.get(), Editor::Webview).await?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(&sb3))
+}
+
+/// Build the project in `workspace_path` on the sync worker and record it
+/// as sent to `editor`.
+pub async fn project_sb3(workspace_path: PathBuf, editor: Editor) -> Result<Vec<u8>, String> {
+    let job = sync::submit(JobKind::Other, move |_| {
+        let result = workspace::build_sb3(&workspace_path);
+        status::record_build(&workspace_path, &result);
+        let sb3 = result.map_err(|e| e.to_string())?;
+        history::record(&workspace_path, &sb3, "workspace");
+        if let Some(hash) = workspace::sb3_project_hash(&sb3) {
+            status::record_sent(&workspace_path, hash);
+        }
+        sync::set_sent(editor, &sb3);
+        Ok::<_, String>(sb3)
+    });
+    wait_for(job).await.ok_or(WORKER_STOPPED)?
+}
 
+/// The SB3 last sent to the editors. `sb3-updated` carries no data; the
+/// editor fetches the project with this.
 #[tauri::command]
-pub fn get_initial_sb3(state: State<'_, WorkspacePath>) -> Result<String, String> {
-    let sb3 = workspace::build_sb3(&state.0).ok_or("Failed to build SB3")?;
-    let encoded = base64::engine::general_purpose::STANDARD.encode(&sb3);
-    Ok(encoded)
+pub fn get_updated_sb3() -> Result<Response, String> {
+    updated_sb3(Editor::Webview).map(Response::new)
+}
+
+/// The SB3 last sent to the editors, fetched by `editor`.
+pub fn updated_sb3(editor: Editor) -> Result<Vec<u8>, String> {
+    sync::fetch_sent(editor).ok_or_else(|| "No project has been sent to the editor yet".to_string())
 }
 
+/// Save the SB3 sent as the raw request body. Resolves once the save is
+/// applied (or superseded by a newer save).
 #[tauri::command]
-pub fn save_project_from_editor(
+pub async fn save_project_sb3(
+    state: State<'_, WorkspacePath>,
+    request: Request<'_>,
+) -> Result<(), String> {
+    let InvokeBody::Raw(data) = request.body() else {
+        return Err("Expected the SB3 as a binary request body".to_string());
+    };
+    save_from_editor(state.get(), data.clone(), Editor::Webview).await
+}
+
+/// save_project_sb3 with the SB3 as base64, for clients without binary IPC.
+#[tauri::command]
+pub async fn save_project_from_editor(
     state: State<'_, WorkspacePath>,
     sb3_base64: String,
 ) -> Result<(), String> {
-    let data = base64::engine::general_purpose::STANDARD
-        .decode(&sb3_base64)
-        .map_err(|e| format!("Base64 decode error: {}", e))?;
+    let data = decode_sb3(&sb3_base64)?;
+    save_from_editor(state.get(), data, Editor::Webview).await
+}
 
+pub fn decode_sb3(sb3_base64: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(sb3_base64)
+        .map_err(|e| format!("Base64 decode error: {}", e))
+}
+
+/// Save an SB3 from any editor, including browsers connected to the local
+/// server.
+pub async fn save_from_editor(
+    workspace_path: PathBuf,
+    data: Vec<u8>,
+    editor: Editor,
+) -> Result<(), String> {
     log::info!(
         "[live-scratch] received sb3 from editor ({} bytes)",
         data.len()
     );
 
-    watcher::set_ignore(true);
-    workspace::extract_sb3(&state.0, &data);
+    let job = sync::submit(JobKind::EditorSave(editor), move |app| {
+        if !sync::is_current(app, &workspace_path) {
+            return Err("The project was switched before the save was applied".to_string());
+        }
+        sync::apply_editor_save(app, &workspace_path, &data, editor)
+    });
+    wait_for(job).await.unwrap_or(Ok(()))
+}
 
-    // Reset ignore flag after a delay (matches server.js behavior)
-    std::thread::spawn(|| {
-        std::thread::sleep(std::time::Duration::from_millis(1000));
-        watcher::set_ignore(false);
+/// Switch the workspace between a single project.json and the split layout
+/// (project.meta.json + targets/<name>.json).
+#[tauri::command]
+pub async fn set_split_layout(
+    state: State<'_, WorkspacePath>,
+    enabled: bool,
+) -> Result<(), String> {
+    let workspace_path = state.get();
+    let job = sync::submit(JobKind::Other, move |_| {
+        layout::set_split(&workspace_path, enabled)
     });
+    wait_for(job).await.ok_or(WORKER_STOPPED)?
+}
 
-    Ok(())
+/// Check the workspace project against the SB3 schema.
+#[tauri::command]
+pub async fn validate_project(state: State<'_, WorkspacePath>) -> Result<Vec<Diagnostic>, String> {
+    let workspace_path = state.get();
+    let job = sync::submit(JobKind::Other, move |_| {
+        workspace::validate_workspace(&workspace_path)
+    });
+    wait_for(job).await.ok_or_else(|| WORKER_STOPPED.to_string())
+}
+
+/// Move costume and sound files the project no longer uses to
+/// .live-scratch/backups/. Returns the moved files.
+#[tauri::command]
+pub async fn clean_unused_assets(state: State<'_, WorkspacePath>) -> Result<Vec<String>, String> {
+    let workspace_path = state.get();
+    let job = sync::submit(JobKind::Other, move |_| {
+        workspace::clean_unused_assets(&workspace_path)
+    });
+    wait_for(job).await.ok_or(WORKER_STOPPED)?
+}
+
+/// Called by the editor after each vm.loadProject or applied patch with its
+/// outcome, so a project that builds but is rejected by scratch-vm is
+/// visible outside the webview (log and .live-scratch/status.json).
+/// `project_hash` is the hash a `project-patch` carried; loads of a fetched
+/// SB3 leave it out.
+#[tauri::command]
+pub fn report_editor_load(
+    state: State<'_, WorkspacePath>,
+    ok: bool,
+    error: Option<String>,
+    duration_ms: f64,
+    project_hash: Option<String>,
+) {
+    editor_loaded(
+        &state.get(),
+        Editor::Webview,
+        ok,
+        error,
+        duration_ms,
+        project_hash,
+    );
+}
+
+/// report_editor_load from `editor`.
+pub fn editor_loaded(
+    workspace_path: &Path,
+    editor: Editor,
+    ok: bool,
+    error: Option<String>,
+    duration_ms: f64,
+    project_hash: Option<String>,
+) {
+    if ok {
+        log::info!("[live-scratch] editor loaded project ({:.0} ms)", duration_ms);
+    } else {
+        log::error!(
+            "[live-scratch] editor failed to load project ({:.0} ms): {}",
+            duration_ms,
+            error.as_deref().unwrap_or("unknown error")
+        );
+    }
+    let project_hash = sync::confirm_load(editor, ok, project_hash);
+    status::record_editor_load(workspace_path, ok, error, duration_ms, project_hash);
+}
+
+/// Projects in the workspace root, with the open one marked current.
+#[tauri::command]
+pub fn list_projects(state: State<'_, WorkspacePath>) -> Vec<ProjectInfo> {
+    let current = state.project_name();
+    projects::list_projects(&state.root)
+        .into_iter()
+        .map(|(name, dir)| ProjectInfo {
+            current: name == current,
+            path: dir.to_string_lossy().to_string(),
+            name,
+        })
+        .collect()
+}
+
+/// Open another project of the workspace (creating it from the default
+/// project if the folder doesn't exist yet) and load it into the editor.
+#[tauri::command]
+pub async fn switch_project(name: String) -> Result<(), String> {
+    let job = sync::submit(JobKind::Other, move |app| sync::switch_project(app, &name));
+    wait_for(job).await.ok_or(WORKER_STOPPED)?
 }
 
 #[tauri::command]
 pub fn get_workspace_path(state: State<'_, WorkspacePath>) -> String {
-    state.0.to_string_lossy().to_string()
+    state.get().to_string_lossy().to_string()
+}
+
+/// Persist the workspace directory used from the next launch on (None
+/// restores the default). --workspace and LIVE_SCRATCH_WORKSPACE still
+/// take precedence.
+#[tauri::command]
+pub fn set_workspace_location(path: Option<String>) -> Result<(), String> {
+    let mut current = settings::load();
+    current.workspace = path.filter(|p| !p.is_empty()).map(PathBuf::from);
+    settings::save(&current)?;
+    log::info!("[live-scratch] workspace setting: {:?}", current.workspace);
+    Ok(())
 }
 
 #[tauri::command]
@@ -68,30 +296,45 @@ pub async fn open_sb3_file(
     let path = file_path.as_path().ok_or("Invalid file path")?;
     let data = fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
 
-    watcher::set_ignore(true);
-    workspace::extract_sb3(&state.0, &data);
-
-    // Build new SB3 and emit to frontend
-    if let Some(sb3) = workspace::build_sb3(&state.0) {
-        let encoded = base64::engine::general_purpose::STANDARD.encode(&sb3);
-        use tauri::Emitter;
-        app.emit("sb3-updated", &encoded)
-            .map_err(|e| format!("Failed to emit: {}", e))?;
-    }
+    let workspace_path = state.get();
+    let job = sync::submit(JobKind::Other, move |app| {
+        workspace::open_sb3(&workspace_path, &data)?;
+        history::record(&workspace_path, &data, "open");
 
-    std::thread::spawn(|| {
-        std::thread::sleep(std::time::Duration::from_millis(1000));
-        watcher::set_ignore(false);
+        // Build new SB3 and emit to frontend
+        sync::build_and_emit(app, &workspace_path);
+        Ok(())
     });
+    wait_for(job).await.ok_or(WORKER_STOPPED)?
+}
 
-    Ok(())
+/// Snapshots taken on every editor save, SB3 open and restore, newest first.
+#[tauri::command]
+pub fn list_snapshots(state: State<'_, WorkspacePath>) -> Vec<Snapshot> {
+    history::list_snapshots(&state.get())
+}
+
+/// Replace the workspace project with a snapshot and load it into the editor.
+#[tauri::command]
+pub async fn restore_snapshot(state: State<'_, WorkspacePath>, id: String) -> Result<(), String> {
+    let workspace_path = state.get();
+    let job = sync::submit(JobKind::Other, move |app| {
+        history::restore(&workspace_path, &id)?;
+        sync::build_and_emit(app, &workspace_path);
+        Ok(())
+    });
+    wait_for(job).await.ok_or(WORKER_STOPPED)?
 }
 
+/// Export the project to a file chosen by the user, deflated at `level`
+/// (0 to 9; default: the `export_level` setting). Returns the file's sizes
+/// against the Scratch website's limits, or None if the user cancelled.
 #[tauri::command]
 pub async fn export_sb3_file(
     app: tauri::AppHandle,
     state: State<'_, WorkspacePath>,
-) -> Result<(), String> {
+    level: Option<u32>,
+) -> Result<Option<SizeReport>, String> {
     use tauri_plugin_dialog::DialogExt;
 
     let file_path = app
@@ -101,23 +344,52 @@ pub async fn export_sb3_file(
         .set_file_name("project.sb3")
         .blocking_save_file();
 
-    let file_path = match file_path {
+    let file_path = match file_path.and_then(|f| f.into_path().ok()) {
         Some(f) => f,
-        None => return Ok(()), // User cancelled
+        None => return Ok(None), // User cancelled
     };
 
-    let sb3 = workspace::build_sb3(&state.0).ok_or("Failed to build SB3")?;
-    fs::write(file_path.as_path().unwrap(), &sb3)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
+    let workspace_path = state.get();
+    let level = level.unwrap_or_else(settings::export_level);
+    let job = sync::submit(JobKind::Other, move |_| {
+        workspace::build_sb3_compressed(&workspace_path, level)
+    });
+    let sb3 = wait_for(job)
+        .await
+        .ok_or(WORKER_STOPPED)?
+        .map_err(|e| e.to_string())?;
+    write_export(&file_path, &sb3).map(Some)
+}
+
+/// Write an exported SB3 and report its sizes.
+pub fn write_export(path: &Path, sb3: &[u8]) -> Result<SizeReport, String> {
+    fs::write(path, sb3).map_err(|e| format!("Failed to write file: {}", e))?;
+    let report = export::size_report(sb3)?;
+    log::info!("[live-scratch] exported SB3 to {:?} ({} bytes)", path, report.bytes);
+    for problem in &report.over_limits {
+        log::warn!("[live-scratch] exported project won't upload to Scratch: {}", problem);
+    }
+    Ok(report)
+}
 
-    log::info!("[live-scratch] exported SB3 to {:?}", file_path);
+/// Persist the deflate level used by Export SB3 (None restores the
+/// default).
+#[tauri::command]
+pub fn set_export_level(level: Option<u32>) -> Result<(), String> {
+    if level.is_some_and(|level| level > export::MAX_LEVEL) {
+        return Err(format!("The level must be 0 to {}", export::MAX_LEVEL));
+    }
+    let mut current = settings::load();
+    current.export_level = level;
+    settings::save(&current)?;
+    log::info!("[live-scratch] export level setting: {:?}", current.export_level);
     Ok(())
 }
 
 #[tauri::command]
 pub fn open_workspace_in_finder(state: State<'_, WorkspacePath>) -> Result<(), String> {
     std::process::Command::new("explorer")
-        .arg(&*state.0)
+        .arg(state.get())
         .spawn()
         .map_err(|e| format!("Failed to open Explorer: {}", e))?;
     Ok(())
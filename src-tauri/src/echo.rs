@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::assets;
+
+/// What the backend last did to each path it touched in the workspace: the
+/// MD5 of the content it wrote, or None if it removed (or moved away) the
+/// file. The watcher drops events for paths still in that state, so our own
+/// writes don't come back as edits while real edits always get through.
+static WRITTEN: Mutex<Option<HashMap<PathBuf, Option<String>>>> = Mutex::new(None);
+
+fn remember(path: &Path, hash: Option<String>) {
+    WRITTEN
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(path.to_path_buf(), hash);
+}
+
+/// fs::rename, remembering that `from` is gone and what `to` now holds.
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    remember(from, None);
+    if let Ok(content) = fs::read(from) {
+        remember(to, Some(assets::md5_hex(&content)));
+    }
+    fs::rename(from, to)
+}
+
+/// fs::remove_file, remembering that the file is gone.
+pub fn remove_file(path: &Path) -> io::Result<()> {
+    remember(path, None);
+    fs::remove_file(path)
+}
+
+/// Whether the current state of `path` is the one the backend left it in.
+/// A path whose state differs is forgotten, so later events for it count.
+pub fn is_echo(path: &Path) -> bool {
+    let mut written = WRITTEN.lock().unwrap();
+    let Some(written) = written.as_mut() else {
+        return false;
+    };
+    let Some(expected) = written.get(path).cloned() else {
+        return false;
+    };
+    // A folder (e.g. one moved back into place) is never our echo
+    if path.is_dir() {
+        written.remove(path);
+        return false;
+    }
+    let actual = fs::read(path).ok().map(|content| assets::md5_hex(&content));
+    if expected == actual {
+        return true;
+    }
+    written.remove(path);
+    false
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+use std::io::Cursor;
+
+use serde::Serialize;
+
+/// Deflate level used for exports unless one is chosen (0 = stored, 9 =
+/// smallest)
+pub const DEFAULT_LEVEL: u32 = 6;
+pub const MAX_LEVEL: u32 = 9;
+
+/// Largest project.json the Scratch website accepts
+const SCRATCH_MAX_PROJECT_BYTES: u64 = 5_000_000;
+/// Largest costume or sound the Scratch website accepts
+const SCRATCH_MAX_ASSET_BYTES: u64 = 10_000_000;
+
+/// Sizes of an exported SB3, checked against the Scratch website's limits
+/// (which apply to each file inside, uncompressed).
+#[derive(Serialize, Clone)]
+pub struct SizeReport {
+    /// Size of the SB3 file
+    pub bytes: u64,
+    /// Size of the files inside, uncompressed
+    pub uncompressed_bytes: u64,
+    pub project_json_bytes: u64,
+    /// Name and size of the largest costume or sound, if any
+    pub largest_asset: Option<(String, u64)>,
+    /// Each file over a limit, e.g. "project.json is 6.2 MB (limit 5.0 MB)"
+    pub over_limits: Vec<String>,
+}
+
+/// Measure an SB3 built by workspace::build_sb3_compressed.
+pub fn size_report(sb3: &[u8]) -> Result<SizeReport, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(sb3))
+        .map_err(|e| format!("Failed to open SB3 zip: {}", e))?;
+    let mut report = SizeReport {
+        bytes: sb3.len() as u64,
+        uncompressed_bytes: 0,
+        project_json_bytes: 0,
+        largest_asset: None,
+        over_limits: Vec::new(),
+    };
+    for i in 0..archive.len() {
+        let file = archive
+            .by_index_raw(i)
+            .map_err(|e| format!("Failed to read zip entry {}: {}", i, e))?;
+        let (name, size) = (file.name().to_string(), file.size());
+        report.uncompressed_bytes += size;
+
+        let limit = if name == crate::layout::PROJECT_JSON {
+            report.project_json_bytes = size;
+            SCRATCH_MAX_PROJECT_BYTES
+        } else {
+            if report
+                .largest_asset
+                .as_ref()
+                .is_none_or(|(_, largest)| size > *largest)
+            {
+                report.largest_asset = Some((name.clone(), size));
+            }
+            SCRATCH_MAX_ASSET_BYTES
+        };
+        if size > limit {
+            report.over_limits.push(format!(
+                "{} is {} (limit {})",
+                name,
+                megabytes(size),
+                megabytes(limit)
+            ));
+        }
+    }
+    Ok(report)
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({} uncompressed)",
+            megabytes(self.bytes),
+            megabytes(self.uncompressed_bytes)
+        )?;
+        writeln!(f, "project.json: {}", megabytes(self.project_json_bytes))?;
+        if let Some((name, size)) = &self.largest_asset {
+            writeln!(f, "largest asset: {} ({})", name, megabytes(*size))?;
+        }
+        if self.over_limits.is_empty() {
+            write!(f, "Within the Scratch website's size limits")
+        } else {
+            write!(
+                f,
+                "Over the Scratch website's size limits:\n{}",
+                self.over_limits.join("\n")
+            )
+        }
+    }
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::status;
+use crate::workspace;
+
+/// Folder under .live-scratch holding the snapshots
+const HISTORY_DIR: &str = "history";
+/// Keep at most this many snapshots of each source...
+const MAX_SNAPSHOTS: usize = 100;
+/// ...taking up at most this many bytes in total (the newest is always kept)
+const MAX_TOTAL_BYTES: u64 = 256 * 1024 * 1024;
+
+/// A snapshot is the SB3 that was applied to the workspace (or, for
+/// "conflict", the editor's version that could not be), stored as
+/// .live-scratch/history/<timestamp>-<source>.sb3
+#[derive(Serialize, Clone)]
+pub struct Snapshot {
+    /// File name without extension, passed to restore_snapshot
+    pub id: String,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// "workspace", "editor", "merge", "conflict", "open" or "restore"
+    pub source: String,
+    pub size: u64,
+}
+
+pub fn history_dir(workspace: &Path) -> PathBuf {
+    workspace.join(status::STATE_DIR).join(HISTORY_DIR)
+}
+
+/// Store `sb3` as the newest snapshot of `source`, unless it is identical
+/// to the current newest one, then apply the retention limits of `source`.
+/// Returns the id of the snapshot holding `sb3`.
+pub fn record(workspace: &Path, sb3: &[u8], source: &str) -> Option<String> {
+    let dir = history_dir(workspace);
+    if let Some(latest) = list_snapshots(workspace).first() {
+        // Only reuse a snapshot of the same source, which is pruned along
+        // with the ones made for `source` (conflicts refer to theirs by id)
+        if latest.source == source
+            && fs::read(snapshot_path(workspace, &latest.id)).is_ok_and(|d| d == sb3)
+        {
+            return Some(latest.id.clone());
+        }
+    }
+    if let Err(err) = fs::create_dir_all(&dir) {
+        log::error!("Failed to create {:?}: {}", dir, err);
+        return None;
+    }
+    let id = format!("{}-{}", status::now_ms(), source);
+    let path = snapshot_path(workspace, &id);
+    if let Err(err) = fs::write(&path, sb3) {
+        log::error!("Failed to write snapshot {:?}: {}", path, err);
+        return None;
+    }
+    log::info!("[live-scratch] snapshot saved: {:?}", path);
+    prune(workspace, source);
+    Some(id)
+}
+
+/// Snapshots of the workspace, newest first.
+pub fn list_snapshots(workspace: &Path) -> Vec<Snapshot> {
+    let Ok(entries) = fs::read_dir(history_dir(workspace)) else {
+        return Vec::new();
+    };
+    let mut snapshots: Vec<Snapshot> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("sb3") {
+                return None;
+            }
+            let id = path.file_stem()?.to_str()?.to_string();
+            let (timestamp, source) = id.split_once('-')?;
+            Some(Snapshot {
+                timestamp: timestamp.parse().ok()?,
+                source: source.to_string(),
+                size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                id,
+            })
+        })
+        .collect();
+    snapshots.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then(b.id.cmp(&a.id)));
+    snapshots
+}
+
+/// Replace the workspace project with a snapshot (archiving the current
+/// files like opening an SB3 does). The restore is itself recorded as a
+/// snapshot so it can be undone. Returns the restored SB3.
+pub fn restore(workspace: &Path, id: &str) -> Result<Vec<u8>, String> {
+    if !list_snapshots(workspace).iter().any(|s| s.id == id) {
+        return Err(format!("No such snapshot: {}", id));
+    }
+    let path = snapshot_path(workspace, id);
+    let data = fs::read(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    workspace::open_sb3(workspace, &data)?;
+    record(workspace, &data, "restore");
+    log::info!("[live-scratch] restored snapshot {}", id);
+    Ok(data)
+}
+
+pub fn snapshot_path(workspace: &Path, id: &str) -> PathBuf {
+    history_dir(workspace).join(format!("{}.sb3", id))
+}
+
+/// Delete the oldest snapshots of `source` beyond MAX_SNAPSHOTS or
+/// MAX_TOTAL_BYTES. Each source has its own limits, so a burst of workspace
+/// builds never deletes the only copy of an editor version kept as a
+/// conflict.
+fn prune(workspace: &Path, source: &str) {
+    let snapshots = list_snapshots(workspace);
+    let mut total = 0;
+    for (i, snapshot) in snapshots.iter().filter(|s| s.source == source).enumerate() {
+        total += snapshot.size;
+        if i == 0 || (i < MAX_SNAPSHOTS && total <= MAX_TOTAL_BYTES) {
+            continue;
+        }
+        let path = snapshot_path(workspace, &snapshot.id);
+        if let Err(err) = fs::remove_file(&path) {
+            log::warn!("[live-scratch] failed to remove old snapshot {:?}: {}", path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_snapshots_never_push_out_a_conflict() {
+        let ws = std::env::temp_dir().join(format!("live-scratch-history-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&ws);
+        let conflict = record(&ws, b"rejected", "conflict").unwrap();
+        for i in 0..=MAX_SNAPSHOTS {
+            // Snapshot ids are millisecond timestamps
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            record(&ws, format!("build {}", i).as_bytes(), "workspace");
+        }
+
+        let snapshots = list_snapshots(&ws);
+        assert!(snapshots.iter().any(|s| s.id == conflict));
+        let builds = snapshots.iter().filter(|s| s.source == "workspace").count();
+        assert_eq!(builds, MAX_SNAPSHOTS);
+        fs::remove_dir_all(&ws).unwrap();
+    }
+
+    #[test]
+    fn identical_snapshots_are_only_shared_within_a_source() {
+        let ws =
+            std::env::temp_dir().join(format!("live-scratch-history-dup-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&ws);
+        let build = record(&ws, b"project", "workspace").unwrap();
+        assert_eq!(record(&ws, b"project", "workspace").unwrap(), build);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let conflict = record(&ws, b"project", "conflict").unwrap();
+        assert_ne!(conflict, build);
+        assert!(conflict.ends_with("-conflict"));
+        fs::remove_dir_all(&ws).unwrap();
+    }
+}
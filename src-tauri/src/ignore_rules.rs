@@ -0,0 +1,233 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// Gitignore-style file in the workspace listing files that aren't part of
+/// the project: changes to them don't trigger rebuilds and builds don't
+/// look at them
+pub const IGNORE_FILE: &str = ".livescratchignore";
+
+/// Rules applied before the workspace's own, which can override them with
+/// `!pattern`: notes, version control, OS metadata and the temporary files
+/// of editors and tools
+const DEFAULT_RULES: &[&str] = &[
+    "*.md",
+    ".git/",
+    ".gitignore",
+    ".gitattributes",
+    ".hg/",
+    ".svn/",
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+    // vim swap, backup and write-test files; emacs lock and autosave files
+    "*.swp",
+    "*.swo",
+    "*~",
+    "4913",
+    ".#*",
+    // A leading # would start a comment
+    "\\#*#",
+    // Atomic saves (VS Code, JetBrains, ...) and unfinished downloads
+    "*.tmp",
+    "*.crswap",
+    "*___jb_tmp___",
+    "*___jb_old___",
+    "*.crdownload",
+    "*.part",
+    ".vscode/",
+    ".idea/",
+    "node_modules/",
+];
+
+/// Whether the default rules ignore a folder named `name` in the root of
+/// any workspace (e.g. node_modules), so the app must not create one.
+pub fn is_ignored_by_default(name: &str) -> bool {
+    static DEFAULTS: OnceLock<Gitignore> = OnceLock::new();
+    let rules = DEFAULTS.get_or_init(|| {
+        let mut builder = GitignoreBuilder::new("");
+        for rule in DEFAULT_RULES {
+            let _ = builder.add_line(None, rule);
+        }
+        builder.build().unwrap_or_else(|_| Gitignore::empty())
+    });
+    rules.matched(name, true).is_ignore()
+}
+
+/// The ignore rules of a workspace: the defaults plus its .livescratchignore.
+pub struct IgnoreRules {
+    workspace: PathBuf,
+    rules: Gitignore,
+}
+
+impl IgnoreRules {
+    /// Read the rules of `workspace`. Invalid lines are logged and skipped.
+    pub fn load(workspace: &Path) -> Self {
+        let mut builder = GitignoreBuilder::new(workspace);
+        for rule in DEFAULT_RULES {
+            if let Err(err) = builder.add_line(None, rule) {
+                log::error!("[live-scratch] invalid default ignore rule {:?}: {}", rule, err);
+            }
+        }
+        let path = workspace.join(IGNORE_FILE);
+        if path.is_file() {
+            if let Some(err) = builder.add(&path) {
+                log::warn!("[live-scratch] {}: {}", IGNORE_FILE, err);
+            }
+        }
+        let rules = builder.build().unwrap_or_else(|err| {
+            log::warn!("[live-scratch] ignoring {}: {}", IGNORE_FILE, err);
+            Gitignore::empty()
+        });
+        IgnoreRules {
+            workspace: workspace.to_path_buf(),
+            rules,
+        }
+    }
+
+    /// Whether `path` (absolute, or relative to the workspace) or a folder
+    /// containing it is ignored. The ignore file itself never is, so edits
+    /// to it take effect.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.workspace).unwrap_or(path);
+        if relative.is_absolute() || relative == Path::new(IGNORE_FILE) {
+            return false;
+        }
+        let mut is_dir = self.workspace.join(relative).is_dir();
+        // Like Gitignore::matched_path_or_any_parents, but without trying
+        // the workspace itself ("" matches rules such as `{}`)
+        for ancestor in relative.ancestors() {
+            if ancestor.as_os_str().is_empty() {
+                break;
+            }
+            let matched = self.rules.matched(ancestor, is_dir);
+            if !matched.is_none() {
+                return matched.is_ignore();
+            }
+            is_dir = true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A fresh workspace folder under the system temp dir, with `rules` as
+    /// its ignore file if given.
+    fn temp_workspace(name: &str, rules: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "live-scratch-ignore-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        if let Some(rules) = rules {
+            fs::write(dir.join(IGNORE_FILE), rules).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn defaults_ignore_notes_tools_and_temporary_files() {
+        let ws = temp_workspace("defaults", None);
+        let rules = IgnoreRules::load(&ws);
+        for path in [
+            "CLAUDE.md",
+            "Sprite1/notes.md",
+            ".git/HEAD",
+            ".gitignore",
+            ".gitattributes",
+            ".hg/store/data",
+            ".svn/entries",
+            ".DS_Store",
+            "Sprite1/.DS_Store",
+            "Thumbs.db",
+            "desktop.ini",
+            "project.json.swp",
+            "project.json.swo",
+            "project.json~",
+            "4913",
+            ".#project.json",
+            "#project.json#",
+            "project.json.tmp",
+            "project.json.crswap",
+            "project.json___jb_tmp___",
+            "project.json___jb_old___",
+            "cat.png.crdownload",
+            "cat.png.part",
+            ".vscode/settings.json",
+            ".idea/workspace.xml",
+            "node_modules/pkg/index.js",
+        ] {
+            assert!(rules.is_ignored(Path::new(path)), "{} is not ignored", path);
+        }
+        for path in [
+            "project.json",
+            "Sprite1/costume1.svg",
+            "targets/Sprite1.json",
+        ] {
+            assert!(!rules.is_ignored(Path::new(path)), "{} is ignored", path);
+        }
+        fs::remove_dir_all(&ws).unwrap();
+    }
+
+    #[test]
+    fn files_in_ignored_folders_stay_ignored_once_deleted() {
+        let ws = temp_workspace("deleted", None);
+        fs::create_dir_all(ws.join(".git")).unwrap();
+        let rules = IgnoreRules::load(&ws);
+        // Git creates and deletes index.lock on every commit
+        assert!(!ws.join(".git/index.lock").exists());
+        assert!(rules.is_ignored(&ws.join(".git/index.lock")));
+        assert!(rules.is_ignored(&ws.join("node_modules/pkg/gone.js")));
+        fs::remove_dir_all(&ws).unwrap();
+    }
+
+    #[test]
+    fn workspace_rules_can_override_the_defaults() {
+        let ws = temp_workspace("override", Some("!README.md\nsketches/\n*.psd\n"));
+        let rules = IgnoreRules::load(&ws);
+        assert!(!rules.is_ignored(Path::new("README.md")));
+        assert!(rules.is_ignored(Path::new("NOTES.md")));
+        assert!(rules.is_ignored(Path::new("sketches/cat.png")));
+        assert!(rules.is_ignored(Path::new("Sprite1/cat.psd")));
+        assert!(!rules.is_ignored(Path::new("Sprite1/cat.png")));
+        fs::remove_dir_all(&ws).unwrap();
+    }
+
+    #[test]
+    fn accepts_absolute_and_relative_paths() {
+        let ws = temp_workspace("paths", None);
+        let rules = IgnoreRules::load(&ws);
+        assert!(rules.is_ignored(&ws.join("CLAUDE.md")));
+        assert!(rules.is_ignored(Path::new("CLAUDE.md")));
+        assert!(!rules.is_ignored(&ws.join("project.json")));
+        assert!(!rules.is_ignored(Path::new("project.json")));
+        // Paths outside the workspace aren't ours to ignore
+        assert!(!rules.is_ignored(&std::env::temp_dir().join("CLAUDE.md")));
+        fs::remove_dir_all(&ws).unwrap();
+    }
+
+    #[test]
+    fn ignore_file_is_never_ignored() {
+        let ws = temp_workspace("self", Some("*\n.*\n"));
+        let rules = IgnoreRules::load(&ws);
+        assert!(rules.is_ignored(Path::new("project.json")));
+        assert!(!rules.is_ignored(Path::new(IGNORE_FILE)));
+        assert!(!rules.is_ignored(&ws.join(IGNORE_FILE)));
+        fs::remove_dir_all(&ws).unwrap();
+    }
+
+    #[test]
+    fn default_folder_check_matches_the_rules() {
+        assert!(is_ignored_by_default("node_modules"));
+        assert!(is_ignored_by_default("notes.md"));
+        assert!(!is_ignored_by_default("Sprite1"));
+        assert!(!is_ignored_by_default("node_modules (2)"));
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::echo;
+use crate::status;
+
+/// Folder under .live-scratch holding the journal of the update in progress
+const JOURNAL_DIR: &str = "journal";
+const JOURNAL_FILE: &str = "journal.json";
+/// One byte is appended (and synced) after each step is applied, so a
+/// replay resumes after the steps already done
+const PROGRESS_FILE: &str = "progress";
+const STAGED_DIR: &str = "staged";
+
+/// One step of an update. Paths are relative to the workspace.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Op {
+    /// Move a file or folder (e.g. into a backup)
+    Move { from: String, to: String },
+    /// Replace `path` with the staged file of the same index
+    Write { path: String, staged: String },
+    Remove { path: String },
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Journal {
+    ops: Vec<Op>,
+}
+
+/// A multi-file update of the workspace that either fully happens or not
+/// at all, even across a crash or power loss:
+///
+/// 1. new contents are staged under .live-scratch/journal/staged/
+/// 2. commit writes journal.json listing every step (the commit point)
+/// 3. the steps are applied, each a rename or a removal, and counted in
+///    the progress file
+/// 4. the journal is deleted
+///
+/// A crash before 2 leaves the old project (recover discards the staged
+/// files); a crash after 2 is rolled forward by recover, starting at the
+/// first step not counted as done.
+pub struct Transaction {
+    workspace: PathBuf,
+    journal: Journal,
+}
+
+impl Transaction {
+    pub fn begin(workspace: &Path) -> Result<Self, String> {
+        recover(workspace);
+        let staged = journal_dir(workspace).join(STAGED_DIR);
+        fs::create_dir_all(&staged).map_err(|e| format!("Failed to create {:?}: {}", staged, e))?;
+        Ok(Transaction {
+            workspace: workspace.to_path_buf(),
+            journal: Journal::default(),
+        })
+    }
+
+    pub fn workspace(&self) -> &Path {
+        &self.workspace
+    }
+
+    /// Stage `content` to be written to `path`.
+    pub fn write(&mut self, path: &Path, content: &[u8]) -> Result<(), String> {
+        let staged = self.journal.ops.len().to_string();
+        let staged_path = journal_dir(&self.workspace).join(STAGED_DIR).join(&staged);
+        write_synced(&staged_path, content)?;
+        self.journal.ops.push(Op::Write {
+            path: self.relative(path)?,
+            staged,
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Result<(), String> {
+        self.journal.ops.push(Op::Remove {
+            path: self.relative(path)?,
+        });
+        Ok(())
+    }
+
+    pub fn move_to(&mut self, from: &Path, to: &Path) -> Result<(), String> {
+        self.journal.ops.push(Op::Move {
+            from: self.relative(from)?,
+            to: self.relative(to)?,
+        });
+        Ok(())
+    }
+
+    /// Write the journal and apply it.
+    pub fn commit(self) -> Result<(), String> {
+        self.save()?;
+        let result = apply(&self.workspace, &self.journal);
+        // On failure the journal stays for recover to finish the job
+        if result.is_ok() {
+            discard(&self.workspace);
+        }
+        result
+    }
+
+    /// Write journal.json: the commit point.
+    fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_vec(&self.journal)
+            .map_err(|e| format!("Failed to serialize journal: {}", e))?;
+        let path = journal_dir(&self.workspace).join(JOURNAL_FILE);
+        let tmp = path.with_extension("json.tmp");
+        write_synced(&tmp, &json)?;
+        fs::rename(&tmp, &path).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    fn relative(&self, path: &Path) -> Result<String, String> {
+        path.strip_prefix(&self.workspace)
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .map_err(|_| format!("{:?} is outside the workspace", path))
+    }
+}
+
+/// Finish or discard an update interrupted by a crash. Called before every
+/// transaction and when a project is opened.
+pub fn recover(workspace: &Path) {
+    let dir = journal_dir(workspace);
+    if !dir.exists() {
+        return;
+    }
+    let journal_path = dir.join(JOURNAL_FILE);
+    let journal = fs::read(&journal_path)
+        .ok()
+        .and_then(|content| serde_json::from_slice::<Journal>(&content).ok());
+    if let Some(journal) = journal {
+        log::warn!("[live-scratch] completing an interrupted workspace update");
+        if let Err(err) = apply(workspace, &journal) {
+            log::error!("[live-scratch] failed to complete interrupted update: {}", err);
+            return;
+        }
+    }
+    discard(workspace);
+}
+
+/// Apply the steps of a journal not yet counted in the progress file, so
+/// this can be repeated after a crash. Steps done earlier must not run
+/// again: a later step may have put a new file where an earlier one moved
+/// the old one from. Each step is also skipped when its effect is already
+/// there, for the one step that may have been done but not yet counted.
+fn apply(workspace: &Path, journal: &Journal) -> Result<(), String> {
+    let dir = journal_dir(workspace);
+    let staged_dir = dir.join(STAGED_DIR);
+    let progress_path = dir.join(PROGRESS_FILE);
+    let done = fs::metadata(&progress_path).map_or(0, |m| m.len() as usize);
+    let mut progress = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&progress_path)
+        .map_err(|e| format!("Failed to open {:?}: {}", progress_path, e))?;
+    for op in journal.ops.iter().skip(done) {
+        match op {
+            Op::Move { from, to } => {
+                let (from, to) = (workspace.join(from), workspace.join(to));
+                if from.exists() && !to.exists() {
+                    create_parent(&to)?;
+                    echo::rename(&from, &to)
+                        .map_err(|e| format!("Failed to move {:?} to {:?}: {}", from, to, e))?;
+                }
+            }
+            Op::Write { path, staged } => {
+                let (staged, path) = (staged_dir.join(staged), workspace.join(path));
+                if staged.exists() {
+                    create_parent(&path)?;
+                    echo::rename(&staged, &path)
+                        .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+                }
+            }
+            Op::Remove { path } => {
+                let path = workspace.join(path);
+                if path.exists() {
+                    echo::remove_file(&path)
+                        .map_err(|e| format!("Failed to remove {:?}: {}", path, e))?;
+                }
+            }
+        }
+        progress
+            .write_all(b".")
+            .and_then(|_| progress.sync_all())
+            .map_err(|e| format!("Failed to write {:?}: {}", progress_path, e))?;
+    }
+    Ok(())
+}
+
+fn discard(workspace: &Path) {
+    let dir = journal_dir(workspace);
+    if let Err(err) = fs::remove_dir_all(&dir) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            log::error!("Failed to remove {:?}: {}", dir, err);
+        }
+    }
+}
+
+fn journal_dir(workspace: &Path) -> PathBuf {
+    workspace.join(status::STATE_DIR).join(JOURNAL_DIR)
+}
+
+fn create_parent(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(dir) => fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e)),
+        None => Ok(()),
+    }
+}
+
+/// Write a file and flush it to disk before returning.
+fn write_synced(path: &Path, content: &[u8]) -> Result<(), String> {
+    let mut file =
+        fs::File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+    file.write_all(content)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh workspace folder under the system temp dir.
+    fn temp_workspace(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "live-scratch-journal-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Save and apply the journal of `tx` like commit, but leave it in
+    /// place, as if the app stopped before deleting it.
+    fn apply_without_discard(tx: &Transaction) {
+        tx.save().unwrap();
+        apply(&tx.workspace, &tx.journal).unwrap();
+    }
+
+    #[test]
+    fn replaying_move_then_write_to_same_path_keeps_both_versions() {
+        let ws = temp_workspace("move-write");
+        fs::write(ws.join("project.json"), "old").unwrap();
+        let backup = ws.join("backup/project.json");
+
+        let mut tx = Transaction::begin(&ws).unwrap();
+        tx.move_to(&ws.join("project.json"), &backup).unwrap();
+        tx.write(&ws.join("project.json"), b"new").unwrap();
+        apply_without_discard(&tx);
+        recover(&ws);
+
+        assert_eq!(fs::read_to_string(ws.join("project.json")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "old");
+        assert!(!journal_dir(&ws).exists());
+        fs::remove_dir_all(&ws).unwrap();
+    }
+
+    #[test]
+    fn replaying_folder_move_then_write_into_it_completes() {
+        let ws = temp_workspace("folder");
+        fs::create_dir_all(ws.join("Sprite1")).unwrap();
+        fs::write(ws.join("Sprite1/a.svg"), "old").unwrap();
+
+        let mut tx = Transaction::begin(&ws).unwrap();
+        tx.move_to(&ws.join("Sprite1"), &ws.join("backup/Sprite1"))
+            .unwrap();
+        tx.write(&ws.join("Sprite1/a.svg"), b"new").unwrap();
+        apply_without_discard(&tx);
+        recover(&ws);
+
+        assert_eq!(fs::read_to_string(ws.join("Sprite1/a.svg")).unwrap(), "new");
+        assert_eq!(
+            fs::read_to_string(ws.join("backup/Sprite1/a.svg")).unwrap(),
+            "old"
+        );
+        assert!(!journal_dir(&ws).exists());
+        fs::remove_dir_all(&ws).unwrap();
+    }
+
+    #[test]
+    fn replay_redoes_a_step_done_but_not_counted() {
+        let ws = temp_workspace("uncounted");
+        fs::write(ws.join("project.json"), "old").unwrap();
+        let backup = ws.join("backup/project.json");
+
+        let mut tx = Transaction::begin(&ws).unwrap();
+        tx.move_to(&ws.join("project.json"), &backup).unwrap();
+        tx.write(&ws.join("project.json"), b"new").unwrap();
+        tx.remove(&ws.join("stale.svg")).unwrap();
+        fs::write(ws.join("stale.svg"), "stale").unwrap();
+        apply_without_discard(&tx);
+        // Stopped after the write was done but before it was counted
+        fs::write(ws.join("stale.svg"), "stale").unwrap();
+        fs::write(journal_dir(&ws).join(PROGRESS_FILE), ".").unwrap();
+        recover(&ws);
+
+        assert_eq!(fs::read_to_string(ws.join("project.json")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "old");
+        assert!(!ws.join("stale.svg").exists());
+        fs::remove_dir_all(&ws).unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::ignore_rules::IgnoreRules;
+use crate::journal::Transaction;
+use crate::status;
+use crate::validate::Diagnostic;
+
+/// Monolithic project file (the layout used inside every SB3)
+pub const PROJECT_JSON: &str = "project.json";
+/// Split layout: everything except the targets, plus the target file order
+pub const META_JSON: &str = "project.meta.json";
+/// Split layout: one pretty-printed JSON file per target (stage and sprites)
+pub const TARGETS_DIR: &str = "targets";
+
+/// A workspace uses the split layout when project.meta.json exists.
+pub fn is_split(workspace: &Path) -> bool {
+    workspace.join(META_JSON).is_file()
+}
+
+/// Whether the workspace holds a project in either layout.
+pub fn has_project(workspace: &Path) -> bool {
+    workspace.join(PROJECT_JSON).is_file() || is_split(workspace)
+}
+
+/// Whether a change to `path` can affect the build of the project in
+/// `workspace`. Our own state files (e.g. .live-scratch/status.json),
+/// ignored files (e.g. CLAUDE.md, see ignore_rules), other projects nested
+/// in this one's folder and bare folders don't.
+pub fn is_project_change(workspace: &Path, ignore: &IgnoreRules, path: &Path) -> bool {
+    !path.starts_with(workspace.join(status::STATE_DIR))
+        && !ignore.is_ignored(path)
+        && !in_nested_project(workspace, path)
+        && !path.is_dir()
+}
+
+/// Whether `path` lies in a subfolder of `workspace` that is a project of
+/// its own (the root project of a multi-project workspace).
+fn in_nested_project(workspace: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(workspace) else {
+        return false;
+    };
+    let mut components = relative.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => has_project(&workspace.join(first)),
+        _ => false,
+    }
+}
+
+/// A project read from the workspace, plus where each target came from.
+pub struct ProjectSource {
+    pub project: Value,
+    /// The file holding everything outside the targets
+    pub root_file: String,
+    /// For each target: the file holding it and the JSON pointer of the
+    /// target within that file ("" when the file is the target itself)
+    pub target_files: Vec<(String, String)>,
+}
+
+impl ProjectSource {
+    /// Map a JSON pointer into the assembled project to the workspace file
+    /// that holds the value and the pointer within that file.
+    pub fn locate(&self, pointer: &str) -> (String, String) {
+        if let Some(rest) = pointer.strip_prefix("/targets/") {
+            let (index, tail) = match rest.split_once('/') {
+                Some((index, tail)) => (index, format!("/{}", tail)),
+                None => (rest, String::new()),
+            };
+            if let Some((file, prefix)) = index
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| self.target_files.get(i))
+            {
+                return (file.clone(), format!("{}{}", prefix, tail));
+            }
+        }
+        (self.root_file.clone(), pointer.to_string())
+    }
+}
+
+/// Read the project from the workspace, assembling the split target files
+/// into a single project.json value when the split layout is in use.
+pub fn read_project(workspace: &Path) -> Result<Value, String> {
+    load_project(workspace)
+        .map(|source| source.project)
+        .map_err(|diag| diag.to_string())
+}
+
+/// Like read_project, but keeps track of which file each target came from
+/// and reports syntax errors with their file, line and column.
+pub fn load_project(workspace: &Path) -> Result<ProjectSource, Diagnostic> {
+    if !is_split(workspace) {
+        let project = read_json(workspace, PROJECT_JSON)?;
+        let target_files = project
+            .get("targets")
+            .and_then(Value::as_array)
+            .map_or(0, |targets| targets.len());
+        let target_files = (0..target_files)
+            .map(|i| (PROJECT_JSON.to_string(), format!("/targets/{}", i)))
+            .collect();
+        return Ok(ProjectSource {
+            project,
+            root_file: PROJECT_JSON.to_string(),
+            target_files,
+        });
+    }
+
+    let mut meta = match read_json(workspace, META_JSON)? {
+        Value::Object(map) => map,
+        _ => return Err(Diagnostic::new(META_JSON, "", "must contain a JSON object")),
+    };
+
+    // "targets" lists the target files in layer order. Inline target objects
+    // are accepted too, so a hand-written meta file still assembles.
+    let listed = match meta.remove("targets") {
+        Some(Value::Array(items)) => items,
+        None => Vec::new(),
+        Some(_) => return Err(Diagnostic::new(META_JSON, "/targets", "must be an array")),
+    };
+
+    let mut targets = Vec::new();
+    let mut target_files = Vec::new();
+    let mut seen = HashSet::new();
+    for (i, item) in listed.into_iter().enumerate() {
+        match item {
+            Value::String(file_name) => {
+                let rel = format!("{}/{}", TARGETS_DIR, file_name);
+                targets.push(read_json(workspace, &rel)?);
+                target_files.push((rel, String::new()));
+                seen.insert(file_name);
+            }
+            Value::Object(_) => {
+                targets.push(item);
+                target_files.push((META_JSON.to_string(), format!("/targets/{}", i)));
+            }
+            _ => {
+                return Err(Diagnostic::new(
+                    META_JSON,
+                    &format!("/targets/{}", i),
+                    "must be a target file name",
+                ))
+            }
+        }
+    }
+
+    // Target files that are not listed yet (e.g. a sprite added by hand)
+    // are appended in name order.
+    for file_name in list_target_files(workspace) {
+        if !seen.contains(&file_name) {
+            let rel = format!("{}/{}", TARGETS_DIR, file_name);
+            targets.push(read_json(workspace, &rel)?);
+            target_files.push((rel, String::new()));
+        }
+    }
+
+    meta.insert("targets".to_string(), Value::Array(targets));
+    Ok(ProjectSource {
+        project: Value::Object(meta),
+        root_file: META_JSON.to_string(),
+        target_files,
+    })
+}
+
+/// Stage writing the project into the workspace using the layout it
+/// already has. project.json is pretty-printed; in the split layout every
+/// target is written to its own file and target files that no longer exist
+/// are removed.
+pub fn write_project(tx: &mut Transaction, project: &Value) -> Result<(), String> {
+    let workspace = tx.workspace().to_path_buf();
+    if is_split(&workspace) {
+        write_split(tx, &workspace, project)
+    } else {
+        write_pretty(tx, &workspace.join(PROJECT_JSON), project)
+    }
+}
+
+/// Convert the workspace between the monolithic and the split layout.
+pub fn set_split(workspace: &Path, enabled: bool) -> Result<(), String> {
+    if enabled == is_split(workspace) {
+        return Ok(());
+    }
+
+    let project = read_project(workspace)?;
+    let mut tx = Transaction::begin(workspace)?;
+    if enabled {
+        write_split(&mut tx, workspace, &project)?;
+        tx.remove(&workspace.join(PROJECT_JSON))?;
+        tx.commit()?;
+    } else {
+        write_pretty(&mut tx, &workspace.join(PROJECT_JSON), &project)?;
+        for file_name in list_target_files(workspace) {
+            tx.remove(&workspace.join(TARGETS_DIR).join(file_name))?;
+        }
+        tx.remove(&workspace.join(META_JSON))?;
+        tx.commit()?;
+        fs::remove_dir(workspace.join(TARGETS_DIR)).ok();
+    }
+
+    log::info!(
+        "[live-scratch] switched workspace to {} layout",
+        if enabled { "split" } else { "single-file" }
+    );
+    Ok(())
+}
+
+/// Make a target, costume or sound name safe to use as a file name. A
+/// leading '.' becomes '_', so no name gives a hidden file or folder
+/// (such as .git or .live-scratch).
+pub fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let trimmed = cleaned.trim().trim_end_matches('.');
+    if trimmed.is_empty() {
+        "_".to_string()
+    } else if let Some(rest) = trimmed.strip_prefix('.') {
+        format!("_{}", rest)
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn write_split(tx: &mut Transaction, workspace: &Path, project: &Value) -> Result<(), String> {
+    let mut meta = match project {
+        Value::Object(map) => map.clone(),
+        _ => return Err("project.json must contain a JSON object".to_string()),
+    };
+    let targets = match meta.remove("targets") {
+        Some(Value::Array(items)) => items,
+        _ => Vec::new(),
+    };
+
+    let targets_dir = workspace.join(TARGETS_DIR);
+
+    let mut file_names = Vec::new();
+    let mut taken = HashSet::new();
+    for target in &targets {
+        let base = target
+            .get("name")
+            .and_then(Value::as_str)
+            .map(sanitize_file_name)
+            .unwrap_or_else(|| "target".to_string());
+        let file_name = unique_file_name(&base, "json", &mut taken);
+        write_pretty(tx, &targets_dir.join(&file_name), target)?;
+        file_names.push(Value::String(file_name));
+    }
+
+    for file_name in list_target_files(workspace) {
+        if !taken.contains(&file_name.to_lowercase()) {
+            tx.remove(&targets_dir.join(file_name))?;
+        }
+    }
+
+    meta.insert("targets".to_string(), Value::Array(file_names));
+    write_pretty(tx, &workspace.join(META_JSON), &Value::Object(meta))
+}
+
+/// Pick "<base>.<ext>", or "<base> (2).<ext>" etc. if the name is taken.
+/// Names are compared case-insensitively since Windows file names are.
+pub fn unique_file_name(base: &str, ext: &str, taken: &mut HashSet<String>) -> String {
+    let with_ext = |name: String| {
+        if ext.is_empty() {
+            name
+        } else {
+            format!("{}.{}", name, ext)
+        }
+    };
+    let mut file_name = with_ext(base.to_string());
+    let mut n = 2;
+    while taken.contains(&file_name.to_lowercase()) {
+        file_name = with_ext(format!("{} ({})", base, n));
+        n += 1;
+    }
+    taken.insert(file_name.to_lowercase());
+    file_name
+}
+
+/// *.json files in targets/, sorted by name.
+fn list_target_files(workspace: &Path) -> Vec<String> {
+    let mut names: Vec<String> = match fs::read_dir(workspace.join(TARGETS_DIR)) {
+        Ok(entries) => entries
+            .flatten()
+            .filter(|e| e.path().is_file())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|name| name.ends_with(".json"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+    names
+}
+
+fn read_json(workspace: &Path, rel: &str) -> Result<Value, Diagnostic> {
+    let content = fs::read(workspace.join(rel))
+        .map_err(|e| Diagnostic::new(rel, "", &format!("failed to read: {}", e)))?;
+    serde_json::from_slice(&content).map_err(|e| Diagnostic {
+        file: rel.to_string(),
+        pointer: String::new(),
+        line: Some(e.line()),
+        column: Some(e.column()),
+        message: format!("JSON syntax error: {}", e),
+    })
+}
+
+fn write_pretty(tx: &mut Transaction, path: &Path, value: &Value) -> Result<(), String> {
+    let pretty = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize {:?}: {}", path, e))?;
+    tx.write(path, pretty.as_bytes())
+}
@@ -1,13 +1,50 @@
+pub mod assets;
+mod backup;
+#[cfg(feature = "app")]
 mod commands;
+pub mod echo;
+pub mod export;
+#[cfg(feature = "app")]
+mod history;
+pub mod ignore_rules;
+pub mod journal;
+pub mod layout;
+#[cfg(feature = "app")]
+mod merge;
+#[cfg(feature = "app")]
+mod patch;
+#[cfg(feature = "app")]
+mod projects;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "app")]
+mod settings;
+mod status;
+#[cfg(feature = "app")]
+mod sync;
+pub mod validate;
+#[cfg(feature = "app")]
 mod watcher;
-mod workspace;
+pub mod workspace;
 
+// The desktop app; everything above is also used by the headless CLI
+#[cfg(feature = "app")]
 use std::fs;
 
+#[cfg(feature = "app")]
 use commands::WorkspacePath;
+#[cfg(feature = "app")]
+use sync::JobKind;
+#[cfg(feature = "app")]
 use tauri::menu::{MenuBuilder, MenuItemBuilder, SubmenuBuilder};
-use tauri::{Emitter, Manager};
+#[cfg(feature = "app")]
+use tauri::{AppHandle, Manager};
 
+/// Menu item id prefix for the File > Switch Project entries
+#[cfg(feature = "app")]
+const SWITCH_PROJECT_ID: &str = "switch_project:";
+
+#[cfg(feature = "app")]
 pub fn run() {
     env_logger::init();
 
@@ -15,13 +52,21 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .setup(|app| {
-            // Determine workspace path
-            let app_data = dirs::document_dir()
-                .expect("Failed to get Documents directory")
-                .join("Live Scratch");
-            fs::create_dir_all(&app_data).expect("Failed to create workspace directory");
+            // Determine workspace path (--workspace, LIVE_SCRATCH_WORKSPACE,
+            // settings, then the default locations)
+            let args: Vec<String> = std::env::args().collect();
+            let (workspace_root, source) = settings::resolve_workspace(&args);
+            if let Err(err) = fs::create_dir_all(&workspace_root) {
+                return Err(
+                    format!("Failed to create workspace {:?}: {}", workspace_root, err).into(),
+                );
+            }
 
-            log::info!("[live-scratch] workspace: {:?}", app_data);
+            log::info!("[live-scratch] workspace: {:?} (from {})", workspace_root, source);
+
+            // Open the last used project of the workspace (or its first one)
+            let (project_name, app_data) = projects::initial_project(&workspace_root);
+            log::info!("[live-scratch] project: {:?} ({:?})", project_name, app_data);
 
             // Find default-project directory, trying multiple locations:
             // 1. Tauri resource dir (production bundle)
@@ -60,6 +105,9 @@ pub fn run() {
                 }
             };
 
+            // Finish (or discard) an update interrupted by a crash
+            journal::recover(&app_data);
+
             // Ensure default project exists
             workspace::ensure_default_project(&app_data, &default_project_dir);
 
@@ -67,149 +115,306 @@ pub fn run() {
             workspace::ensure_claude_md(&app_data, &default_project_dir);
 
             // Store workspace path in state
-            app.manage(WorkspacePath(app_data.clone()));
+            app.manage(WorkspacePath::new(
+                workspace_root,
+                default_project_dir,
+                project_name,
+                app_data.clone(),
+            ));
 
             // Build initial SB3
             match workspace::build_sb3(&app_data) {
-                Some(_) => log::info!("[live-scratch] initial SB3 built successfully"),
-                None => log::warn!("[live-scratch] initial SB3 build failed"),
+                Ok(_) => log::info!("[live-scratch] initial SB3 built successfully"),
+                Err(err) => log::warn!("[live-scratch] initial SB3 build failed: {}", err),
             }
 
-            // Start file watcher
-            watcher::start_watcher(app.handle().clone(), app_data);
-
-            // Build macOS menu
-            let app_handle = app.handle();
-
-            let open_sb3 = MenuItemBuilder::with_id("open_sb3", "Open SB3...")
-                .accelerator("CmdOrCtrl+O")
-                .build(app_handle)?;
-            let export_sb3 = MenuItemBuilder::with_id("export_sb3", "Export SB3...")
-                .accelerator("CmdOrCtrl+S")
-                .build(app_handle)?;
-            let show_workspace =
-                MenuItemBuilder::with_id("show_workspace", "Show Workspace in Explorer")
-                    .accelerator("CmdOrCtrl+Shift+O")
-                    .build(app_handle)?;
-
-            let file_menu = SubmenuBuilder::new(app_handle, "File")
-                .item(&open_sb3)
-                .item(&export_sb3)
-                .separator()
-                .item(&show_workspace)
-                .build()?;
-
-            let edit_menu = SubmenuBuilder::new(app_handle, "Edit")
-                .undo()
-                .redo()
-                .separator()
-                .cut()
-                .copy()
-                .paste()
-                .select_all()
-                .build()?;
-
-            let view_menu = SubmenuBuilder::new(app_handle, "View")
-                .fullscreen()
-                .build()?;
-
-            let window_menu = SubmenuBuilder::new(app_handle, "Window")
-                .minimize()
-                .close_window()
-                .build()?;
-
-            let menu = MenuBuilder::new(app_handle)
-                .item(&file_menu)
-                .item(&edit_menu)
-                .item(&view_menu)
-                .item(&window_menu)
-                .build()?;
-
-            app.set_menu(menu)?;
-
-            // Handle menu events
-            let app_handle_clone = app.handle().clone();
-            app.on_menu_event(move |_app, event| {
-                let id = event.id().0.as_str();
-                match id {
-                    "open_sb3" => {
-                        let handle = app_handle_clone.clone();
-                        std::thread::spawn(move || {
-                            let state = handle.state::<WorkspacePath>();
-                            use tauri_plugin_dialog::DialogExt;
-
-                            let file_path = handle
-                                .dialog()
-                                .file()
-                                .add_filter("Scratch 3 Project", &["sb3"])
-                                .blocking_pick_file();
-
-                            if let Some(file_resp) = file_path {
-                                let Some(path) = file_resp.as_path() else { return; };
-                                if let Ok(data) = fs::read(path) {
-                                    watcher::set_ignore(true);
-                                    workspace::extract_sb3(&state.0, &data);
-
-                                    if let Some(sb3) = workspace::build_sb3(&state.0) {
-                                        let encoded = base64::Engine::encode(
-                                            &base64::engine::general_purpose::STANDARD,
-                                            &sb3,
-                                        );
-                                        let _ = handle.emit("sb3-updated", &encoded);
-                                    }
-
-                                    std::thread::spawn(|| {
-                                        std::thread::sleep(std::time::Duration::from_millis(1000));
-                                        watcher::set_ignore(false);
-                                    });
-                                }
-                            }
-                        });
-                    }
-                    "export_sb3" => {
-                        let handle = app_handle_clone.clone();
-                        std::thread::spawn(move || {
-                            let state = handle.state::<WorkspacePath>();
-                            use tauri_plugin_dialog::DialogExt;
-
-                            let file_path = handle
-                                .dialog()
-                                .file()
-                                .add_filter("Scratch 3 Project", &["sb3"])
-                                .set_file_name("project.sb3")
-                                .blocking_save_file();
-
-                            if let Some(file_resp) = file_path {
-                                if let Some(sb3) = workspace::build_sb3(&state.0) {
-                                    if let Some(path) = file_resp.as_path() {
-                                        let _ = fs::write(path, &sb3);
-                                        log::info!("[live-scratch] exported SB3 to {:?}", path);
-                                    }
-                                }
-                            }
-                        });
-                    }
-                    "show_workspace" => {
-                        let handle = app_handle_clone.clone();
-                        let state = handle.state::<WorkspacePath>();
-                        let _ = std::process::Command::new("explorer")
-                            .arg(&*state.0)
-                            .spawn();
-                    }
-                    _ => {}
-                }
-            });
+            // Start the sync worker and the file watcher feeding it
+            sync::start_worker(app.handle().clone());
+            watcher::start_watcher(app_data);
+
+            // Serve the editor to browsers if asked to (--serve, settings)
+            if let Some(config) = settings::resolve_server(&args) {
+                #[cfg(feature = "server")]
+                server::start(app.handle().clone(), &config);
+                #[cfg(not(feature = "server"))]
+                log::warn!(
+                    "[live-scratch] ignoring server on port {}: built without the server feature",
+                    config.port
+                );
+            }
+
+            set_app_menu(app.handle())?;
+            app.on_menu_event(|app, event| handle_menu_event(app, event.id().0.as_str()));
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
+            commands::get_project_sb3,
             commands::get_initial_sb3,
+            commands::get_updated_sb3,
+            commands::save_project_sb3,
             commands::save_project_from_editor,
+            commands::list_projects,
+            commands::switch_project,
             commands::get_workspace_path,
+            commands::set_workspace_location,
+            commands::set_split_layout,
+            commands::validate_project,
+            commands::clean_unused_assets,
+            commands::report_editor_load,
             commands::open_sb3_file,
             commands::export_sb3_file,
+            commands::set_export_level,
+            commands::list_snapshots,
+            commands::restore_snapshot,
             commands::open_workspace_in_finder,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+/// Build the app menu and install it. Called again after switching projects
+/// to refresh File > Switch Project.
+#[cfg(feature = "app")]
+pub(crate) fn set_app_menu(app_handle: &AppHandle) -> tauri::Result<()> {
+    let state = app_handle.state::<WorkspacePath>();
+    let current_project = state.project_name();
+
+    let open_sb3 = MenuItemBuilder::with_id("open_sb3", "Open SB3...")
+        .accelerator("CmdOrCtrl+O")
+        .build(app_handle)?;
+    let export_sb3 = MenuItemBuilder::with_id("export_sb3", "Export SB3...")
+        .accelerator("CmdOrCtrl+S")
+        .build(app_handle)?;
+    let revert_to = MenuItemBuilder::with_id("revert_to", "Revert To...").build(app_handle)?;
+    let show_workspace = MenuItemBuilder::with_id("show_workspace", "Show Workspace in Explorer")
+        .accelerator("CmdOrCtrl+Shift+O")
+        .build(app_handle)?;
+
+    let mut switch_menu = SubmenuBuilder::new(app_handle, "Switch Project");
+    for (name, _) in projects::list_projects(&state.root) {
+        let label = if name == projects::ROOT_PROJECT {
+            "(Workspace Root)".to_string()
+        } else {
+            name.clone()
+        };
+        let item = MenuItemBuilder::with_id(format!("{}{}", SWITCH_PROJECT_ID, name), label)
+            .enabled(name != current_project)
+            .build(app_handle)?;
+        switch_menu = switch_menu.item(&item);
+    }
+    let new_project = MenuItemBuilder::with_id("new_project", "New Project...").build(app_handle)?;
+    let switch_menu = switch_menu.separator().item(&new_project).build()?;
+
+    let file_menu = SubmenuBuilder::new(app_handle, "File")
+        .item(&open_sb3)
+        .item(&export_sb3)
+        .item(&revert_to)
+        .separator()
+        .item(&switch_menu)
+        .separator()
+        .item(&show_workspace)
+        .build()?;
+
+    let edit_menu = SubmenuBuilder::new(app_handle, "Edit")
+        .undo()
+        .redo()
+        .separator()
+        .cut()
+        .copy()
+        .paste()
+        .select_all()
+        .build()?;
+
+    let view_menu = SubmenuBuilder::new(app_handle, "View")
+        .fullscreen()
+        .build()?;
+
+    let window_menu = SubmenuBuilder::new(app_handle, "Window")
+        .minimize()
+        .close_window()
+        .build()?;
+
+    let menu = MenuBuilder::new(app_handle)
+        .item(&file_menu)
+        .item(&edit_menu)
+        .item(&view_menu)
+        .item(&window_menu)
+        .build()?;
+
+    app_handle.set_menu(menu)?;
+    Ok(())
+}
+
+#[cfg(feature = "app")]
+fn handle_menu_event(app: &AppHandle, id: &str) {
+    if let Some(name) = id.strip_prefix(SWITCH_PROJECT_ID) {
+        let name = name.to_string();
+        sync::submit(JobKind::Other, move |app| {
+            if let Err(err) = sync::switch_project(app, &name) {
+                log::error!("[live-scratch] failed to switch project: {}", err);
+            }
+        });
+        return;
+    }
+
+    match id {
+        "open_sb3" => {
+            let handle = app.clone();
+            std::thread::spawn(move || {
+                let state = handle.state::<WorkspacePath>();
+                use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+
+                let file_path = handle
+                    .dialog()
+                    .file()
+                    .add_filter("Scratch 3 Project", &["sb3"])
+                    .blocking_pick_file();
+
+                if let Some(file_resp) = file_path {
+                    let Some(path) = file_resp.as_path() else { return; };
+                    if let Ok(data) = fs::read(path) {
+                        let workspace_path = state.get();
+                        sync::submit(JobKind::Other, move |app| {
+                            match workspace::open_sb3(&workspace_path, &data) {
+                                Ok(_) => {
+                                    history::record(&workspace_path, &data, "open");
+                                    sync::build_and_emit(app, &workspace_path);
+                                }
+                                Err(err) => {
+                                    log::error!("[live-scratch] failed to open SB3: {}", err);
+                                    app.dialog()
+                                        .message(err)
+                                        .kind(MessageDialogKind::Error)
+                                        .title("Open SB3")
+                                        .show(|_| {});
+                                }
+                            }
+                        });
+                    }
+                }
+            });
+        }
+        "export_sb3" => {
+            let handle = app.clone();
+            std::thread::spawn(move || {
+                let state = handle.state::<WorkspacePath>();
+                use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+
+                let file_path = handle
+                    .dialog()
+                    .file()
+                    .add_filter("Scratch 3 Project", &["sb3"])
+                    .set_file_name("project.sb3")
+                    .blocking_save_file();
+
+                let Some(path) = file_path.and_then(|f| f.into_path().ok()) else { return; };
+                let workspace_path = state.get();
+                let level = settings::export_level();
+                let built = sync::run(JobKind::Other, move |_| {
+                    workspace::build_sb3_compressed(&workspace_path, level)
+                });
+                let result = match built {
+                    Some(Ok(sb3)) => commands::write_export(&path, &sb3),
+                    Some(Err(err)) => Err(err.to_string()),
+                    None => return,
+                };
+                // Tell the user how the file fares against Scratch's limits
+                let (kind, message) = match result {
+                    Ok(report) if report.over_limits.is_empty() => {
+                        (MessageDialogKind::Info, report.to_string())
+                    }
+                    Ok(report) => (MessageDialogKind::Warning, report.to_string()),
+                    Err(err) => {
+                        log::error!("[live-scratch] export failed: {}", err);
+                        (MessageDialogKind::Error, err)
+                    }
+                };
+                handle
+                    .dialog()
+                    .message(message)
+                    .kind(kind)
+                    .title("Export SB3")
+                    .show(|_| {});
+            });
+        }
+        "revert_to" => {
+            let handle = app.clone();
+            std::thread::spawn(move || {
+                let state = handle.state::<WorkspacePath>();
+                use tauri_plugin_dialog::DialogExt;
+
+                let workspace_path = state.get();
+                let history_dir = history::history_dir(&workspace_path);
+                let file_path = handle
+                    .dialog()
+                    .file()
+                    .set_title("Revert To Snapshot")
+                    .set_directory(&history_dir)
+                    .add_filter("Snapshot", &["sb3"])
+                    .blocking_pick_file();
+
+                let Some(path) = file_path.and_then(|f| f.into_path().ok()) else { return; };
+                if path.parent() != Some(history_dir.as_path()) {
+                    log::error!("[live-scratch] not a snapshot: {:?}", path);
+                    return;
+                }
+                let Some(id) = path.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+                    return;
+                };
+
+                sync::submit(JobKind::Other, move |app| {
+                    match history::restore(&workspace_path, &id) {
+                        Ok(_) => {
+                            sync::build_and_emit(app, &workspace_path);
+                        }
+                        Err(err) => log::error!("[live-scratch] failed to revert: {}", err),
+                    }
+                });
+            });
+        }
+        "new_project" => {
+            let handle = app.clone();
+            std::thread::spawn(move || {
+                let state = handle.state::<WorkspacePath>();
+                use tauri_plugin_dialog::DialogExt;
+
+                // There is no text prompt, so the user creates (or picks) a
+                // folder directly inside the workspace root
+                let folder = handle
+                    .dialog()
+                    .file()
+                    .set_title("New Project: create a folder in the workspace")
+                    .set_directory(&state.root)
+                    .blocking_pick_folder();
+
+                let Some(folder) = folder.and_then(|f| f.into_path().ok()) else { return; };
+                let name = match folder.parent() {
+                    Some(parent) if parent == state.root => folder
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    _ => {
+                        log::error!(
+                            "[live-scratch] new project must be a folder directly in {:?}",
+                            state.root
+                        );
+                        return;
+                    }
+                };
+                sync::submit(JobKind::Other, move |app| {
+                    if let Err(err) = sync::switch_project(app, &name) {
+                        log::error!("[live-scratch] failed to create project: {}", err);
+                    }
+                });
+            });
+        }
+        "show_workspace" => {
+            let state = app.state::<WorkspacePath>();
+            let _ = std::process::Command::new("explorer")
+                .arg(state.get())
+                .spawn();
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,314 @@
+use serde_json::{Map, Value};
+
+use crate::validate;
+
+/// Per-target maps merged entry by entry, so edits to different blocks,
+/// variables, etc. of the same sprite don't conflict
+const KEYED_FIELDS: &[&str] = &["blocks", "variables", "lists", "broadcasts", "comments"];
+
+/// Three-way merge of two projects that both changed since `base`: `ours`
+/// is the workspace version, `theirs` the editor's. Targets are matched by
+/// name; within a target, blocks (and variables, lists, broadcasts and
+/// comments) are merged individually and every other field as a whole.
+/// Everything else at the top level (monitors, extensions, meta) is merged
+/// as a whole.
+///
+/// Returns the merged project, or the JSON pointers of what both sides
+/// changed differently (or of what makes the merged project invalid, with
+/// the reason). Pointers name targets rather than index them
+/// (`/targets/Sprite1/blocks/...`), since the two sides may order them
+/// differently.
+pub fn merge_projects(base: &Value, ours: &Value, theirs: &Value) -> Result<Value, Vec<String>> {
+    let mut conflicts = Vec::new();
+    let empty = Map::new();
+    let base_obj = base.as_object().unwrap_or(&empty);
+    let (Some(ours_obj), Some(theirs_obj)) = (ours.as_object(), theirs.as_object()) else {
+        return Err(vec!["project is not a JSON object".to_string()]);
+    };
+
+    let mut merged = Map::new();
+    for key in union_keys(ours_obj, theirs_obj) {
+        let value = if key == "targets" {
+            Some(merge_targets(
+                base_obj.get("targets"),
+                ours_obj.get("targets"),
+                theirs_obj.get("targets"),
+                &mut conflicts,
+            ))
+        } else {
+            merge_value(
+                base_obj.get(&key),
+                ours_obj.get(&key),
+                theirs_obj.get(&key),
+                &format!("/{}", escape(&key)),
+                &mut conflicts,
+            )
+        };
+        if let Some(value) = value {
+            merged.insert(key, value);
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(conflicts);
+    }
+
+    // Blocks merged independently may still not fit together (e.g. both
+    // sides attached a different block after the same one)
+    let merged = Value::Object(merged);
+    let invalid = validate::validate_project(&merged);
+    if !invalid.is_empty() {
+        return Err(invalid
+            .into_iter()
+            .map(|(pointer, message)| {
+                format!("{}: {}", named_pointer(&merged, &pointer), message)
+            })
+            .collect());
+    }
+    Ok(merged)
+}
+
+fn merge_targets(
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    conflicts: &mut Vec<String>,
+) -> Value {
+    let list = |targets: Option<&Value>| -> Vec<Value> {
+        targets.and_then(Value::as_array).cloned().unwrap_or_default()
+    };
+    let (base, ours, theirs) = (list(base), list(ours), list(theirs));
+    let find = |targets: &[Value], name: &str| -> Option<Value> {
+        targets.iter().find(|t| target_name(t) == name).cloned()
+    };
+
+    // The editor's target order, then targets only the workspace has
+    let mut names: Vec<String> = theirs.iter().map(target_name).collect();
+    for name in ours.iter().map(target_name) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    let mut merged = Vec::new();
+    for name in names {
+        let pointer = format!("/targets/{}", escape(&name));
+        let (b, o, t) = (find(&base, &name), find(&ours, &name), find(&theirs, &name));
+        let target = match (&o, &t) {
+            (Some(Value::Object(o)), Some(Value::Object(t))) => {
+                let b = b.as_ref().and_then(Value::as_object).cloned().unwrap_or_default();
+                Some(merge_target(&b, o, t, &pointer, conflicts))
+            }
+            _ => merge_value(b.as_ref(), o.as_ref(), t.as_ref(), &pointer, conflicts),
+        };
+        merged.extend(target);
+    }
+    Value::Array(merged)
+}
+
+fn merge_target(
+    base: &Map<String, Value>,
+    ours: &Map<String, Value>,
+    theirs: &Map<String, Value>,
+    pointer: &str,
+    conflicts: &mut Vec<String>,
+) -> Value {
+    let empty = Map::new();
+    let mut merged = Map::new();
+    for key in union_keys(ours, theirs) {
+        let field_pointer = format!("{}/{}", pointer, escape(&key));
+        let (b, o, t) = (base.get(&key), ours.get(&key), theirs.get(&key));
+        let value = match (o, t) {
+            (Some(Value::Object(o)), Some(Value::Object(t)))
+                if KEYED_FIELDS.contains(&key.as_str()) =>
+            {
+                let b = b.and_then(Value::as_object).unwrap_or(&empty);
+                let mut entries = Map::new();
+                for id in union_keys(o, t) {
+                    let entry_pointer = format!("{}/{}", field_pointer, escape(&id));
+                    if let Some(v) =
+                        merge_value(b.get(&id), o.get(&id), t.get(&id), &entry_pointer, conflicts)
+                    {
+                        entries.insert(id, v);
+                    }
+                }
+                Some(Value::Object(entries))
+            }
+            _ => merge_value(b, o, t, &field_pointer, conflicts),
+        };
+        if let Some(value) = value {
+            merged.insert(key, value);
+        }
+    }
+    Value::Object(merged)
+}
+
+/// Take whichever side changed; a change on both sides is a conflict
+/// unless they agree. None means absent (or deleted).
+fn merge_value(
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    pointer: &str,
+    conflicts: &mut Vec<String>,
+) -> Option<Value> {
+    if ours == theirs || theirs == base {
+        return ours.cloned();
+    }
+    if ours == base {
+        return theirs.cloned();
+    }
+    conflicts.push(pointer.to_string());
+    ours.cloned()
+}
+
+/// Keys of both maps, `theirs` first.
+fn union_keys(ours: &Map<String, Value>, theirs: &Map<String, Value>) -> Vec<String> {
+    let mut keys: Vec<String> = theirs.keys().cloned().collect();
+    keys.extend(ours.keys().filter(|k| !theirs.contains_key(*k)).cloned());
+    keys
+}
+
+/// `pointer` into `project` with the target index replaced by the target's
+/// name, like the pointers of conflicts.
+fn named_pointer(project: &Value, pointer: &str) -> String {
+    let Some(rest) = pointer.strip_prefix("/targets/") else {
+        return pointer.to_string();
+    };
+    let (index, tail) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    let name = index
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| project.get("targets")?.get(i))
+        .map(target_name)
+        .filter(|name| !name.is_empty());
+    match name {
+        Some(name) => format!("/targets/{}{}", escape(&name), tail),
+        None => pointer.to_string(),
+    }
+}
+
+fn target_name(target: &Value) -> String {
+    target
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Escape a JSON pointer token (RFC 6901).
+fn escape(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A valid project whose sprite has `blocks`.
+    fn project(blocks: Value) -> Value {
+        let costume = json!({ "name": "costume1", "assetId": "a", "dataFormat": "svg" });
+        json!({
+            "targets": [
+                { "isStage": true, "name": "Stage", "costumes": [costume], "sounds": [] },
+                {
+                    "isStage": false,
+                    "name": "Sprite1",
+                    "blocks": blocks,
+                    "costumes": [costume],
+                    "sounds": []
+                }
+            ],
+            "monitors": [],
+            "extensions": [],
+            "meta": { "semver": "3.0.0" }
+        })
+    }
+
+    fn block(opcode: &str, next: Option<&str>) -> Value {
+        json!({
+            "opcode": opcode,
+            "next": next,
+            "parent": null,
+            "inputs": {},
+            "fields": {},
+            "shadow": false,
+            "topLevel": true,
+            "x": 0,
+            "y": 0
+        })
+    }
+
+    fn sprite_blocks(project: &Value) -> &Map<String, Value> {
+        project["targets"][1]["blocks"].as_object().unwrap()
+    }
+
+    #[test]
+    fn takes_blocks_changed_on_one_side() {
+        let base =
+            project(json!({ "a": block("motion_movesteps", None), "b": block("looks_say", None) }));
+        let ours =
+            project(json!({ "a": block("motion_turnright", None), "b": block("looks_say", None) }));
+        let theirs = project(
+            json!({ "a": block("motion_movesteps", None), "b": block("looks_think", None) }),
+        );
+
+        let merged = merge_projects(&base, &ours, &theirs).unwrap();
+        let blocks = sprite_blocks(&merged);
+        assert_eq!(blocks["a"]["opcode"], "motion_turnright");
+        assert_eq!(blocks["b"]["opcode"], "looks_think");
+    }
+
+    #[test]
+    fn same_block_changed_on_both_sides_conflicts_unless_they_agree() {
+        let base = project(json!({ "a": block("motion_movesteps", None) }));
+        let ours = project(json!({ "a": block("motion_turnright", None) }));
+        let theirs = project(json!({ "a": block("motion_turnleft", None) }));
+
+        let conflicts = merge_projects(&base, &ours, &theirs).unwrap_err();
+        assert_eq!(conflicts, vec!["/targets/Sprite1/blocks/a".to_string()]);
+
+        let merged = merge_projects(&base, &ours, &ours).unwrap();
+        assert_eq!(sprite_blocks(&merged)["a"]["opcode"], "motion_turnright");
+    }
+
+    #[test]
+    fn deleted_blocks_stay_deleted_unless_changed_on_the_other_side() {
+        let base =
+            project(json!({ "a": block("motion_movesteps", None), "b": block("looks_say", None) }));
+        let ours = project(
+            json!({ "a": block("motion_movesteps", None), "b": block("looks_think", None) }),
+        );
+        let theirs = project(json!({ "b": block("looks_say", None) }));
+
+        let merged = merge_projects(&base, &ours, &theirs).unwrap();
+        let blocks = sprite_blocks(&merged);
+        assert!(!blocks.contains_key("a"));
+        assert_eq!(blocks["b"]["opcode"], "looks_think");
+
+        let edited =
+            project(json!({ "a": block("motion_turnright", None), "b": block("looks_say", None) }));
+        let conflicts = merge_projects(&base, &edited, &theirs).unwrap_err();
+        assert_eq!(conflicts, vec!["/targets/Sprite1/blocks/a".to_string()]);
+    }
+
+    #[test]
+    fn rejects_merges_that_leave_the_project_invalid() {
+        // One side deletes a block the other attaches after another one
+        let base =
+            project(json!({ "a": block("motion_movesteps", None), "b": block("looks_say", None) }));
+        let ours = project(json!({ "a": block("motion_movesteps", None) }));
+        let theirs = project(
+            json!({ "a": block("motion_movesteps", Some("b")), "b": block("looks_say", None) }),
+        );
+
+        let errors = merge_projects(&base, &ours, &theirs).unwrap_err();
+        assert_eq!(
+            errors,
+            ["/targets/Sprite1/blocks/a/next: next block \"b\" does not exist"]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Target fields a patch can change; anything else takes a full reload
+const PATCHED_FIELDS: &[&str] = &["blocks", "variables", "lists", "broadcasts", "comments"];
+
+/// Changes to a project the editor can apply without reloading it.
+#[derive(Serialize)]
+pub struct ProjectPatch {
+    /// project_hash of the patched project, set by the sender
+    pub hash: String,
+    pub targets: Vec<TargetPatch>,
+}
+
+/// Changes to one target, in SB3 format. Fields that didn't change are
+/// left out; the others hold the entries that changed by id (null for
+/// removed ones), except blocks, which are sent whole.
+#[derive(Serialize)]
+pub struct TargetPatch {
+    pub name: String,
+    /// All the blocks of the target, replacing the current ones: the editor
+    /// rebuilds the shadow blocks of inline inputs, so single blocks can't
+    /// be swapped in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variables: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lists: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub broadcasts: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<Map<String, Value>>,
+}
+
+impl ProjectPatch {
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+}
+
+/// The patch turning `old` into `new`, or None if they differ in more than
+/// the blocks, variables, lists, broadcasts and comments of their targets
+/// (e.g. assets, sprite properties, targets added or renamed, monitors or
+/// extensions), which takes a full reload.
+pub fn diff(old: &Value, new: &Value) -> Option<ProjectPatch> {
+    let (old, new) = (old.as_object()?, new.as_object()?);
+    if without(old, &["targets"]) != without(new, &["targets"]) {
+        return None;
+    }
+    let old_targets = old.get("targets")?.as_array()?;
+    let new_targets = new.get("targets")?.as_array()?;
+    if old_targets.len() != new_targets.len() {
+        return None;
+    }
+
+    let mut targets = Vec::new();
+    for (old_target, new_target) in old_targets.iter().zip(new_targets) {
+        let (old_target, new_target) = (old_target.as_object()?, new_target.as_object()?);
+        if without(old_target, PATCHED_FIELDS) != without(new_target, PATCHED_FIELDS) {
+            return None;
+        }
+        let name = new_target.get("name")?.as_str()?.to_string();
+
+        let blocks = new_target.get("blocks").filter(|b| old_target.get("blocks") != Some(*b));
+        let changes_to = |field| changes(old_target.get(field), new_target.get(field));
+        let patch = TargetPatch {
+            name,
+            blocks: blocks.cloned(),
+            variables: changes_to("variables"),
+            lists: changes_to("lists"),
+            broadcasts: changes_to("broadcasts"),
+            comments: changes_to("comments"),
+        };
+        let changed = patch.blocks.is_some()
+            || patch.variables.is_some()
+            || patch.lists.is_some()
+            || patch.broadcasts.is_some()
+            || patch.comments.is_some();
+        if changed {
+            targets.push(patch);
+        }
+    }
+    Some(ProjectPatch {
+        hash: String::new(),
+        targets,
+    })
+}
+
+/// A copy of `map` without `keys`.
+fn without(map: &Map<String, Value>, keys: &[&str]) -> Map<String, Value> {
+    map.iter()
+        .filter(|(key, _)| !keys.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Entries of `new` that differ from `old` (null for removed ones), or None
+/// if there are none.
+fn changes(old: Option<&Value>, new: Option<&Value>) -> Option<Map<String, Value>> {
+    let empty = Map::new();
+    let old = old.and_then(Value::as_object).unwrap_or(&empty);
+    let new = new.and_then(Value::as_object).unwrap_or(&empty);
+    let mut changes = Map::new();
+    for (id, value) in new {
+        if old.get(id) != Some(value) {
+            changes.insert(id.clone(), value.clone());
+        }
+    }
+    for id in old.keys() {
+        if !new.contains_key(id) {
+            changes.insert(id.clone(), Value::Null);
+        }
+    }
+    (!changes.is_empty()).then_some(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn project() -> Value {
+        let costume =
+            json!({ "name": "costume1", "assetId": "a", "md5ext": "a.svg", "dataFormat": "svg" });
+        json!({
+            "targets": [
+                {
+                    "isStage": true,
+                    "name": "Stage",
+                    "variables": { "v1": ["score", 0], "v2": ["lives", 3] },
+                    "lists": {},
+                    "broadcasts": {},
+                    "blocks": {},
+                    "comments": {},
+                    "costumes": [costume],
+                    "sounds": []
+                },
+                {
+                    "isStage": false,
+                    "name": "Sprite1",
+                    "variables": {},
+                    "lists": {},
+                    "broadcasts": {},
+                    "blocks": {
+                        "a": { "opcode": "event_whenflagclicked", "next": null, "topLevel": true }
+                    },
+                    "comments": {},
+                    "costumes": [costume],
+                    "sounds": [],
+                    "x": 0
+                }
+            ],
+            "monitors": [{ "id": "v1", "mode": "default", "visible": true }],
+            "extensions": [],
+            "meta": { "semver": "3.0.0" }
+        })
+    }
+
+    #[test]
+    fn unchanged_projects_give_an_empty_patch() {
+        assert!(diff(&project(), &project()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn variable_changes_are_sent_by_id() {
+        let mut new = project();
+        new["targets"][0]["variables"] = json!({ "v1": ["points", 0] });
+
+        let patch = diff(&project(), &new).unwrap();
+        assert_eq!(patch.targets.len(), 1);
+        let stage = &patch.targets[0];
+        assert_eq!(stage.name, "Stage");
+        assert_eq!(
+            Value::Object(stage.variables.clone().unwrap()),
+            json!({ "v1": ["points", 0], "v2": null })
+        );
+        assert!(stage.blocks.is_none() && stage.lists.is_none() && stage.comments.is_none());
+    }
+
+    #[test]
+    fn block_changes_send_the_target_blocks() {
+        let mut new = project();
+        new["targets"][1]["blocks"]["b"] = json!({ "opcode": "motion_movesteps", "parent": "a" });
+        new["targets"][1]["blocks"]["a"]["next"] = json!("b");
+
+        let patch = diff(&project(), &new).unwrap();
+        assert_eq!(patch.targets.len(), 1);
+        let sprite = &patch.targets[0];
+        assert_eq!(sprite.name, "Sprite1");
+        assert_eq!(sprite.blocks.as_ref(), Some(&new["targets"][1]["blocks"]));
+        assert!(sprite.variables.is_none());
+    }
+
+    #[test]
+    fn target_changes_take_a_full_reload() {
+        let mut reordered = project();
+        reordered["targets"].as_array_mut().unwrap().reverse();
+        assert!(diff(&project(), &reordered).is_none());
+
+        let mut added = project();
+        let sprite = added["targets"][1].clone();
+        added["targets"].as_array_mut().unwrap().push(sprite);
+        added["targets"][2]["name"] = json!("Sprite2");
+        assert!(diff(&project(), &added).is_none());
+
+        let mut renamed = project();
+        renamed["targets"][1]["name"] = json!("Cat");
+        assert!(diff(&project(), &renamed).is_none());
+
+        let mut moved = project();
+        moved["targets"][1]["x"] = json!(10);
+        assert!(diff(&project(), &moved).is_none());
+    }
+
+    #[test]
+    fn asset_and_monitor_changes_take_a_full_reload() {
+        let mut asset = project();
+        asset["targets"][1]["costumes"][0]["md5ext"] = json!("b.svg");
+        assert!(diff(&project(), &asset).is_none());
+
+        let mut monitor = project();
+        monitor["monitors"][0]["visible"] = json!(false);
+        assert!(diff(&project(), &monitor).is_none());
+    }
+}
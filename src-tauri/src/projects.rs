@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::layout;
+use crate::settings;
+
+/// Name of the project created in an empty workspace root
+pub const DEFAULT_PROJECT: &str = "Untitled";
+/// Name under which a project stored directly in the workspace root is
+/// listed (e.g. a workspace from before multiple projects, or a repository
+/// passed with --workspace)
+pub const ROOT_PROJECT: &str = ".";
+
+#[derive(Serialize, Clone)]
+pub struct ProjectInfo {
+    pub name: String,
+    pub path: String,
+    pub current: bool,
+}
+
+/// Projects under the workspace root: the root itself if it holds a
+/// project, then every subfolder that does, in name order.
+pub fn list_projects(root: &Path) -> Vec<(String, PathBuf)> {
+    let mut projects = Vec::new();
+    if layout::has_project(root) {
+        projects.push((ROOT_PROJECT.to_string(), root.to_path_buf()));
+    }
+
+    let mut subfolders: Vec<(String, PathBuf)> = match fs::read_dir(root) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|e| (e.file_name().to_string_lossy().to_string(), e.path()))
+            .filter(|(name, path)| {
+                !name.starts_with('.') && path.is_dir() && layout::has_project(path)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    subfolders.sort();
+    projects.extend(subfolders);
+    projects
+}
+
+/// Directory of the named project; the folder need not exist yet.
+pub fn project_dir(root: &Path, name: &str) -> Result<PathBuf, String> {
+    if name == ROOT_PROJECT {
+        return Ok(root.to_path_buf());
+    }
+    if name.is_empty() || name.starts_with('.') || layout::sanitize_file_name(name) != name {
+        return Err(format!("Invalid project name: {:?}", name));
+    }
+    Ok(root.join(name))
+}
+
+/// The project to open on startup: the last one used if it still exists,
+/// otherwise the first project found, otherwise a new default project.
+pub fn initial_project(root: &Path) -> (String, PathBuf) {
+    let projects = list_projects(root);
+    if let Some(last) = settings::load().project {
+        if let Some(found) = projects.iter().find(|(name, _)| *name == last) {
+            return found.clone();
+        }
+    }
+    match projects.into_iter().next() {
+        Some(first) => first,
+        None => (DEFAULT_PROJECT.to_string(), root.join(DEFAULT_PROJECT)),
+    }
+}
+
+/// Remember the current project for the next launch.
+pub fn remember_project(name: &str) {
+    let mut current = settings::load();
+    current.project = Some(name.to_string());
+    if let Err(err) = settings::save(&current) {
+        log::warn!("[live-scratch] failed to save current project: {}", err);
+    }
+}
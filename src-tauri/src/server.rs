@@ -0,0 +1,546 @@
+use std::io::Read as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::async_runtime::block_on;
+use tauri::{AppHandle, Manager};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::commands::{self, WorkspacePath};
+use crate::settings::ServerSettings;
+use crate::sync::Editor;
+
+/// Script standing in for window.__TAURI__ in browsers, so live-reload.js
+/// talks to this server the way it talks to the app
+const BRIDGE_JS: &str = include_str!("../../client/server-bridge.js");
+const BRIDGE_PATH: &str = "/server-bridge.js";
+/// WebSocket carrying the events the app emits (sb3-updated, ...)
+const EVENTS_PATH: &str = "/events";
+/// Prefix of the HTTP endpoints standing in for Tauri commands
+const API_PREFIX: &str = "/api/";
+/// Header with the id the events socket was given, sent with saves so they
+/// aren't echoed back to the browser that made them
+const CLIENT_HEADER: &str = "X-Live-Scratch-Client";
+/// Largest request body accepted (an SB3, possibly as base64)
+const MAX_BODY_BYTES: u64 = 768 * 1024 * 1024;
+/// Events queued for a browser that doesn't keep up before it is dropped
+const MAX_QUEUED_EVENTS: usize = 16;
+/// Idle time after which a browser is pinged to find out if it is gone
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+/// Cookie holding the access token of a server open to the network
+const TOKEN_COOKIE: &str = "live-scratch-token";
+/// Query parameter of the startup link carrying the access token
+const TOKEN_PARAM: &str = "token";
+
+/// Browsers connected to the events socket
+static CLIENTS: Mutex<Vec<(u64, mpsc::SyncSender<String>)>> = Mutex::new(Vec::new());
+static NEXT_CLIENT: AtomicU64 = AtomicU64::new(1);
+
+/// Who may use the server
+struct Access {
+    port: u16,
+    /// Token every request must carry (as TOKEN_COOKIE, or TOKEN_PARAM to
+    /// set the cookie) when serving to the network; None when only this
+    /// machine can connect
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Event<'a, S: Serialize> {
+    event: &'a str,
+    payload: &'a S,
+}
+
+/// Serve the Scratch editor over HTTP so the project can be edited from any
+/// browser, on this machine or (with `lan`) another one on the network.
+/// Browsers get the app's events over a WebSocket and save through the
+/// same commands as the app's webview. A network server only lets in
+/// browsers that opened the link with its random access token, printed on
+/// startup.
+pub fn start(app: AppHandle, config: &ServerSettings) {
+    let host = if config.lan { "0.0.0.0" } else { "127.0.0.1" };
+    let server = match Server::http((host, config.port)) {
+        Ok(s) => s,
+        Err(err) => {
+            log::error!("Failed to start server on {}:{}: {}", host, config.port, err);
+            return;
+        }
+    };
+    let token = match config.lan.then(new_token).transpose() {
+        Ok(token) => token,
+        Err(err) => {
+            log::error!("Failed to start server: {}", err);
+            return;
+        }
+    };
+    let access = Arc::new(Access {
+        port: config.port,
+        token,
+    });
+    match &access.token {
+        // Printed rather than logged: the link is the only way in
+        Some(token) => println!(
+            "[live-scratch] editor available at http://<this machine>:{}/?{}={}",
+            config.port, TOKEN_PARAM, token
+        ),
+        None => log::info!(
+            "[live-scratch] editor available at http://localhost:{}/",
+            config.port
+        ),
+    }
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let app = app.clone();
+            let access = access.clone();
+            // Saves wait for the sync worker, so don't hold up other requests
+            std::thread::spawn(move || handle(&app, &access, request));
+        }
+    });
+}
+
+/// Send an event to the connected browsers, except `except`.
+pub fn broadcast<S: Serialize>(event: &str, payload: &S, except: Option<Editor>) {
+    let mut clients = CLIENTS.lock().unwrap();
+    if clients.is_empty() {
+        return;
+    }
+    let message = match serde_json::to_string(&Event { event, payload }) {
+        Ok(m) => m,
+        Err(err) => {
+            log::error!("Failed to serialize {}: {}", event, err);
+            return;
+        }
+    };
+    clients.retain(|(id, sender)| {
+        if except == Some(Editor::Browser(*id)) {
+            return true;
+        }
+        match sender.try_send(message.clone()) {
+            Ok(()) => true,
+            Err(mpsc::TrySendError::Full(_)) => {
+                log::warn!("[live-scratch] dropping browser {} (not keeping up)", id);
+                false
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        }
+    });
+}
+
+fn handle(app: &AppHandle, access: &Access, request: Request) {
+    // Keep other websites open in the same browser from driving the app,
+    // directly or through a DNS name rebound to this machine
+    let host = header(&request, "Host");
+    let origin = header(&request, "Origin");
+    if !same_origin(origin.as_deref(), host.as_deref()) || !allowed_host(access, host.as_deref()) {
+        respond(request, 403, "text/plain", b"Forbidden".to_vec());
+        return;
+    }
+    let path = request.url().split('?').next().unwrap_or("/").to_string();
+    if let Some(token) = &access.token {
+        let cookies = header(&request, "Cookie").unwrap_or_default();
+        if !same_token(cookie(&cookies, TOKEN_COOKIE), token) {
+            if !same_token(query_param(request.url(), TOKEN_PARAM), token) {
+                let message = "Open the link with the access token printed when the app started";
+                respond(request, 403, "text/plain", message.as_bytes().to_vec());
+                return;
+            }
+            // Remember the token and drop it from the address bar
+            let cookie = format!(
+                "{}={}; Path=/; HttpOnly; SameSite=Strict",
+                TOKEN_COOKIE, token
+            );
+            let response = Response::empty(StatusCode(303))
+                .with_header(make_header("Set-Cookie", &cookie))
+                .with_header(make_header("Location", &path));
+            if let Err(err) = request.respond(response) {
+                log::warn!("[live-scratch] failed to send response: {}", err);
+            }
+            return;
+        }
+    }
+    match (request.method(), path.as_str()) {
+        (Method::Get, EVENTS_PATH) => accept_events(request),
+        (Method::Get, BRIDGE_PATH) => {
+            respond(request, 200, "text/javascript", BRIDGE_JS.as_bytes().to_vec())
+        }
+        (Method::Post, _) if path.starts_with(API_PREFIX) => {
+            let command = path[API_PREFIX.len()..].to_string();
+            invoke(app, request, &command);
+        }
+        (Method::Get, _) => serve_asset(app, request, &path),
+        _ => respond(request, 405, "text/plain", b"Method Not Allowed".to_vec()),
+    }
+}
+
+/// Serve a file of the scratch-gui build bundled with the app. index.html
+/// gets the bridge script added.
+fn serve_asset(app: &AppHandle, request: Request, path: &str) {
+    let path = if path == "/" { "/index.html" } else { path };
+    let asset = if path.contains("..") {
+        None
+    } else {
+        app.asset_resolver().get(path.to_string())
+    };
+    let Some(asset) = asset else {
+        respond(request, 404, "text/plain", b"Not Found".to_vec());
+        return;
+    };
+    let mut body = asset.bytes;
+    if path == "/index.html" {
+        let html = String::from_utf8_lossy(&body);
+        let script = format!("<script src=\"{}\"></script>", BRIDGE_PATH);
+        body = match html.find("</head>") {
+            Some(i) => format!("{}{}{}", &html[..i], script, &html[i..]),
+            None => format!("{}{}", script, html),
+        }
+        .into_bytes();
+    }
+    respond(request, 200, &asset.mime_type, body);
+}
+
+/// What a command sends back: JSON, or an SB3 as raw bytes
+enum Reply {
+    Json(Value),
+    Bytes(Vec<u8>),
+}
+
+/// Run a command for the browser. The body holds the arguments as JSON
+/// (camelCase, like invoke), or the SB3 itself for save_project_sb3; the
+/// response holds the result, or the error with status 500.
+fn invoke(app: &AppHandle, mut request: Request, command: &str) {
+    let editor = header(&request, CLIENT_HEADER)
+        .and_then(|id| id.parse().ok())
+        .map_or(Editor::Browser(0), Editor::Browser);
+    let mut body = Vec::new();
+    if let Err(err) = request
+        .as_reader()
+        .take(MAX_BODY_BYTES)
+        .read_to_end(&mut body)
+    {
+        log::error!("Failed to read request for {}: {}", command, err);
+        return;
+    }
+
+    let (status, reply) = match run_command(app, command, body, editor) {
+        Some(Ok(reply)) => (200, reply),
+        Some(Err(err)) => (500, Reply::Json(Value::String(err))),
+        None => (
+            404,
+            Reply::Json(Value::String(format!("Unknown command: {}", command))),
+        ),
+    };
+    match reply {
+        Reply::Json(value) => respond(
+            request,
+            status,
+            "application/json",
+            value.to_string().into_bytes(),
+        ),
+        Reply::Bytes(bytes) => respond(request, status, "application/octet-stream", bytes),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SaveArgs {
+    sb3_base64: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoadArgs {
+    ok: bool,
+    error: Option<String>,
+    duration_ms: f64,
+    project_hash: Option<String>,
+}
+
+/// The commands live-reload.js uses, plus read-only ones for scripts.
+/// None for any other command.
+fn run_command(
+    app: &AppHandle,
+    command: &str,
+    body: Vec<u8>,
+    editor: Editor,
+) -> Option<Result<Reply, String>> {
+    let state = app.state::<WorkspacePath>();
+    let result = match command {
+        "get_project_sb3" => block_on(commands::project_sb3(state.get(), editor)).map(Reply::Bytes),
+        "get_initial_sb3" => block_on(commands::project_sb3(state.get(), editor))
+            .map(|sb3| json(base64::engine::general_purpose::STANDARD.encode(sb3))),
+        "get_updated_sb3" => commands::updated_sb3(editor).map(Reply::Bytes),
+        "save_project_sb3" => {
+            let save = commands::save_from_editor(state.get(), body, editor);
+            block_on(save).map(|_| json(Value::Null))
+        }
+        "save_project_from_editor" => parse_args::<SaveArgs>(&body)
+            .and_then(|args| commands::decode_sb3(&args.sb3_base64))
+            .and_then(|data| {
+                let save = commands::save_from_editor(state.get(), data, editor);
+                block_on(save).map(|_| json(Value::Null))
+            }),
+        "report_editor_load" => parse_args::<LoadArgs>(&body).map(|args| {
+            commands::editor_loaded(
+                &state.get(),
+                editor,
+                args.ok,
+                args.error,
+                args.duration_ms,
+                args.project_hash,
+            );
+            json(Value::Null)
+        }),
+        "validate_project" => block_on(commands::validate_project(state)).and_then(to_json),
+        "list_projects" => to_json(commands::list_projects(state)),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn parse_args<T: for<'de> Deserialize<'de>>(body: &[u8]) -> Result<T, String> {
+    serde_json::from_slice(body).map_err(|e| format!("Invalid arguments: {}", e))
+}
+
+fn json(value: impl Into<Value>) -> Reply {
+    Reply::Json(value.into())
+}
+
+fn to_json<T: Serialize>(value: T) -> Result<Reply, String> {
+    serde_json::to_value(value)
+        .map(Reply::Json)
+        .map_err(|e| format!("Failed to serialize result: {}", e))
+}
+
+/// Upgrade the request to the events WebSocket. The browser is told its
+/// client id first, then gets every event as {"event", "payload"} JSON.
+fn accept_events(request: Request) {
+    let Some(key) = header(&request, "Sec-WebSocket-Key") else {
+        respond(request, 400, "text/plain", b"Expected a WebSocket".to_vec());
+        return;
+    };
+    let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+    let response = Response::empty(StatusCode(101))
+        .with_header(make_header("Sec-WebSocket-Accept", &accept));
+    let stream = request.upgrade("websocket", response);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    let id = NEXT_CLIENT.fetch_add(1, Ordering::Relaxed);
+    let hello = Event {
+        event: "live-scratch-client",
+        payload: &id,
+    };
+    let (sender, receiver) = mpsc::sync_channel(MAX_QUEUED_EVENTS);
+    if let Ok(hello) = serde_json::to_string(&hello) {
+        let _ = sender.try_send(hello);
+    }
+    CLIENTS.lock().unwrap().push((id, sender));
+    log::info!("[live-scratch] browser {} connected", id);
+
+    // Runs until the browser goes away (a send fails) or is dropped by
+    // broadcast (the queue disconnects). The upgraded stream can't time out
+    // reads, so idle browsers are pinged: writing to a closed one fails.
+    loop {
+        let message = match receiver.recv_timeout(PING_INTERVAL) {
+            Ok(text) => Message::text(text),
+            Err(mpsc::RecvTimeoutError::Timeout) => Message::Ping(Default::default()),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        if socket.send(message).is_err() {
+            break;
+        }
+    }
+    CLIENTS.lock().unwrap().retain(|(other, _)| *other != id);
+    log::info!("[live-scratch] browser {} disconnected", id);
+}
+
+/// Whether a request comes from a page served by us (or not from a browser
+/// page at all): its Origin, if any, must match its Host.
+fn same_origin(origin: Option<&str>, host: Option<&str>) -> bool {
+    let Some(origin) = origin else {
+        return true;
+    };
+    let Some(host) = host else {
+        return false;
+    };
+    origin
+        .split_once("://")
+        .is_some_and(|(_, origin_host)| origin_host == host)
+}
+
+/// Whether the Host of a request is one of ours. A server open only to
+/// this machine answers to localhost names only, so a page whose DNS name
+/// was rebound to 127.0.0.1 is turned away; a network one relies on its
+/// access token instead.
+fn allowed_host(access: &Access, host: Option<&str>) -> bool {
+    if access.token.is_some() {
+        return true;
+    }
+    let Some(host) = host else {
+        return false;
+    };
+    ["localhost", "127.0.0.1", "[::1]"]
+        .iter()
+        .any(|name| host == format!("{}:{}", name, access.port))
+}
+
+/// A random 128-bit token for a network server, from the OS random number
+/// generator.
+fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| format!("Failed to generate access token: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Whether `given` is `token`, taking the same time wherever they differ.
+fn same_token(given: Option<String>, token: &str) -> bool {
+    let Some(given) = given else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The value of cookie `name` in a Cookie header.
+fn cookie(cookies: &str, name: &str) -> Option<String> {
+    cookies.split(';').find_map(|pair| {
+        let (key, value) = pair.trim().split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
+
+fn query_param(url: &str, name: &str) -> Option<String> {
+    let (_, query) = url.split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
+
+fn header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str().to_string())
+}
+
+fn make_header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn respond(request: Request, status: u16, content_type: &str, body: Vec<u8>) {
+    let response = Response::from_data(body)
+        .with_status_code(status)
+        .with_header(make_header("Content-Type", content_type));
+    if let Err(err) = request.respond(response) {
+        log::warn!("[live-scratch] failed to send response: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local() -> Access {
+        Access {
+            port: 8601,
+            token: None,
+        }
+    }
+
+    #[test]
+    fn same_origin_requires_the_origin_to_match_the_host() {
+        assert!(same_origin(None, Some("localhost:8601")));
+        assert!(same_origin(
+            Some("http://localhost:8601"),
+            Some("localhost:8601")
+        ));
+        assert!(!same_origin(
+            Some("http://evil.example"),
+            Some("localhost:8601")
+        ));
+        assert!(!same_origin(
+            Some("http://localhost:8602"),
+            Some("localhost:8601")
+        ));
+        assert!(!same_origin(Some("null"), Some("localhost:8601")));
+        assert!(!same_origin(Some("http://localhost:8601"), None));
+    }
+
+    #[test]
+    fn local_server_only_answers_to_localhost_names_on_its_port() {
+        let access = local();
+        for host in ["localhost:8601", "127.0.0.1:8601", "[::1]:8601"] {
+            assert!(allowed_host(&access, Some(host)), "{} was rejected", host);
+        }
+        // A name rebound to 127.0.0.1, another port, no port, no Host
+        for host in ["evil.example:8601", "localhost:8602", "localhost", "[::1]"] {
+            assert!(!allowed_host(&access, Some(host)), "{} was accepted", host);
+        }
+        assert!(!allowed_host(&access, None));
+    }
+
+    #[test]
+    fn network_server_relies_on_its_token() {
+        let access = Access {
+            port: 8601,
+            token: Some(new_token().unwrap()),
+        };
+        assert!(allowed_host(&access, Some("192.168.1.2:8601")));
+    }
+
+    #[test]
+    fn tokens_are_random_128_bit_hex() {
+        let (a, b) = (new_token().unwrap(), new_token().unwrap());
+        assert_eq!(a.len(), 32);
+        assert!(a.bytes().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn same_token_compares_the_whole_token() {
+        assert!(same_token(Some("abc123".to_string()), "abc123"));
+        assert!(!same_token(Some("abc124".to_string()), "abc123"));
+        assert!(!same_token(Some("abc12".to_string()), "abc123"));
+        assert!(!same_token(Some(String::new()), "abc123"));
+        assert!(!same_token(None, "abc123"));
+    }
+
+    #[test]
+    fn cookie_finds_its_value_among_several() {
+        let cookies = "theme=dark; live-scratch-token=abc; other=x=y";
+        assert_eq!(cookie(cookies, TOKEN_COOKIE).as_deref(), Some("abc"));
+        assert_eq!(cookie(cookies, "other").as_deref(), Some("x=y"));
+        assert_eq!(
+            cookie("live-scratch-token=abc", TOKEN_COOKIE).as_deref(),
+            Some("abc")
+        );
+        assert_eq!(cookie("xlive-scratch-token=abc", TOKEN_COOKIE), None);
+        assert_eq!(cookie("", TOKEN_COOKIE), None);
+    }
+
+    #[test]
+    fn query_param_finds_the_token_alone_or_among_others() {
+        assert_eq!(
+            query_param("/?token=abc", TOKEN_PARAM).as_deref(),
+            Some("abc")
+        );
+        assert_eq!(
+            query_param("/index.html?lang=ja&token=abc&x=1", TOKEN_PARAM).as_deref(),
+            Some("abc")
+        );
+        assert_eq!(query_param("/?mytoken=abc", TOKEN_PARAM), None);
+        assert_eq!(query_param("/token=abc", TOKEN_PARAM), None);
+    }
+}
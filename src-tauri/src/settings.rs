@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Environment variable that overrides the workspace location
+pub const WORKSPACE_ENV: &str = "LIVE_SCRATCH_WORKSPACE";
+/// Command-line flag that overrides the workspace location
+pub const WORKSPACE_ARG: &str = "--workspace";
+/// Command-line flag that starts the local editor server (`--serve` or
+/// `--serve=<port>`)
+pub const SERVE_ARG: &str = "--serve";
+/// Command-line flag that makes the local editor server reachable from
+/// other machines
+pub const LAN_ARG: &str = "--lan";
+/// Port of the local editor server unless one is given
+pub const DEFAULT_SERVER_PORT: u16 = 8601;
+
+/// Persisted app settings, stored in <config dir>/live-scratch/settings.json
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Settings {
+    /// Workspace directory to use when neither --workspace nor
+    /// LIVE_SCRATCH_WORKSPACE is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<PathBuf>,
+    /// Project that was open when the app was last used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// Run the local editor server on every launch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<ServerSettings>,
+    /// Deflate level for Export SB3 (see export::DEFAULT_LEVEL)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export_level: Option<u32>,
+}
+
+/// How to run the local server that lets browsers open the project (see
+/// server.rs).
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ServerSettings {
+    #[serde(default = "default_server_port")]
+    pub port: u16,
+    /// Listen on all network interfaces instead of only localhost
+    #[serde(default)]
+    pub lan: bool,
+}
+
+fn default_server_port() -> u16 {
+    DEFAULT_SERVER_PORT
+}
+
+pub fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("live-scratch").join("settings.json"))
+}
+
+/// Load settings; a missing or unreadable file gives the defaults.
+pub fn load() -> Settings {
+    let Some(path) = settings_path() else {
+        return Settings::default();
+    };
+    match fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|err| {
+            log::warn!("[live-scratch] ignoring invalid settings {:?}: {}", path, err);
+            Settings::default()
+        }),
+        Err(_) => Settings::default(),
+    }
+}
+
+pub fn save(settings: &Settings) -> Result<(), String> {
+    let path = settings_path().ok_or("No configuration directory available")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    }
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+/// Decide where the workspace lives, in order of precedence:
+/// 1. `--workspace <dir>` (or `--workspace=<dir>`) on the command line
+/// 2. the LIVE_SCRATCH_WORKSPACE environment variable
+/// 3. the `workspace` setting
+/// 4. Documents/Live Scratch
+/// 5. the XDG data dir (e.g. ~/.local/share/live-scratch), for headless
+///    Linux and CI machines without a Documents directory
+/// 6. ./Live Scratch
+///
+/// Returns the path and a short description of where it came from.
+pub fn resolve_workspace(args: &[String]) -> (PathBuf, &'static str) {
+    if let Some(dir) = arg_value(args, WORKSPACE_ARG) {
+        return (absolute(Path::new(&dir)), "command line");
+    }
+    if let Some(dir) = std::env::var_os(WORKSPACE_ENV).filter(|v| !v.is_empty()) {
+        return (absolute(Path::new(&dir)), "environment");
+    }
+    if let Some(dir) = load().workspace {
+        return (absolute(&dir), "settings");
+    }
+    if let Some(dir) = dirs::document_dir() {
+        return (dir.join("Live Scratch"), "default");
+    }
+    if let Some(dir) = dirs::data_dir() {
+        return (dir.join("live-scratch"), "default");
+    }
+    (absolute(Path::new("Live Scratch")), "default")
+}
+
+/// Decide whether to run the local editor server: `--serve` or
+/// `--serve=<port>` on the command line (plus `--lan` to accept connections
+/// from other machines), else the `server` setting.
+pub fn resolve_server(args: &[String]) -> Option<ServerSettings> {
+    let lan = args.iter().any(|arg| arg == LAN_ARG);
+    for arg in args {
+        if arg == SERVE_ARG {
+            return Some(ServerSettings {
+                port: DEFAULT_SERVER_PORT,
+                lan,
+            });
+        }
+        if let Some(port) = arg.strip_prefix(SERVE_ARG).and_then(|rest| rest.strip_prefix('=')) {
+            match port.parse() {
+                Ok(port) => return Some(ServerSettings { port, lan }),
+                Err(_) => log::warn!("[live-scratch] ignoring invalid server port {:?}", port),
+            }
+        }
+    }
+    load().server
+}
+
+/// Deflate level for exports: the `export_level` setting, else the default.
+pub fn export_level() -> u32 {
+    load()
+        .export_level
+        .unwrap_or(crate::export::DEFAULT_LEVEL)
+        .min(crate::export::MAX_LEVEL)
+}
+
+/// Value of `--name <value>` or `--name=<value>`.
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == name {
+            return iter.next().cloned();
+        }
+        if let Some(value) = arg.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    std::env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
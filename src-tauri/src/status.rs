@@ -0,0 +1,235 @@
+// The CLI only uses STATE_DIR and now_ms; status.json is written by the app
+#![cfg_attr(not(feature = "app"), allow(dead_code))]
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::validate::Diagnostic;
+use crate::workspace::BuildError;
+
+/// Directory inside the workspace for live-scratch's own files.
+/// The watcher and the builder never treat anything in it as project input.
+pub const STATE_DIR: &str = ".live-scratch";
+const STATUS_FILE: &str = "status.json";
+
+/// Contents of .live-scratch/status.json, rewritten on every change so an
+/// agent can poll it after an edit to see whether the edit made it into
+/// the editor.
+#[derive(Serialize, Clone)]
+pub struct SyncStatus {
+    /// Milliseconds since the Unix epoch of the last update
+    pub timestamp: u64,
+    /// Result of the last workspace build
+    pub build: Option<BuildStatus>,
+    /// Validation errors of the last build (empty when it succeeded)
+    pub diagnostics: Vec<Diagnostic>,
+    /// The project the editor currently has
+    pub editor: Option<EditorStatus>,
+    /// Outcome of the editor's last vm.loadProject, as reported back by it
+    pub load: Option<LoadStatus>,
+    /// Hashes of projects sent to the editors that none has acknowledged yet
+    pub pending: VecDeque<String>,
+    /// Set when an editor save could not be merged with workspace edits
+    pub conflict: Option<ConflictStatus>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct BuildStatus {
+    pub ok: bool,
+    pub timestamp: u64,
+    /// Hash of the built project.json (see workspace::project_hash)
+    pub project_hash: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct EditorStatus {
+    pub timestamp: u64,
+    /// Hash of the project.json the editor has; equal to build.project_hash
+    /// once the last successful build has reached the editor
+    pub project_hash: String,
+    /// "workspace" when the project was sent from the workspace to the
+    /// editor, "editor" when it came from an edit in the editor
+    pub source: &'static str,
+}
+
+#[derive(Serialize, Clone)]
+pub struct LoadStatus {
+    pub ok: bool,
+    pub timestamp: u64,
+    pub duration_ms: f64,
+    /// Hash of the project the editor tried to load
+    pub project_hash: Option<String>,
+    /// Error from vm.loadProject when the editor rejected the project
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ConflictStatus {
+    pub timestamp: u64,
+    /// Snapshot id (see history) of the editor's version, which was not
+    /// applied; the workspace keeps its own version
+    pub snapshot: Option<String>,
+    /// JSON pointers (with target names, e.g. /targets/Sprite1/blocks/a)
+    /// of what both sides changed, or why the merge failed
+    pub conflicts: Vec<String>,
+}
+
+/// Stop tracking unacknowledged loads beyond this (e.g. an editor that
+/// never reports back)
+const MAX_PENDING: usize = 16;
+
+static STATUS: Mutex<SyncStatus> = Mutex::new(SyncStatus {
+    timestamp: 0,
+    build: None,
+    diagnostics: Vec::new(),
+    editor: None,
+    load: None,
+    pending: VecDeque::new(),
+    conflict: None,
+});
+
+/// Record the outcome of a workspace build.
+pub fn record_build(workspace: &Path, result: &Result<Vec<u8>, BuildError>) {
+    let now = now_ms();
+    let mut status = STATUS.lock().unwrap();
+    match result {
+        Ok(sb3) => {
+            status.build = Some(BuildStatus {
+                ok: true,
+                timestamp: now,
+                project_hash: crate::workspace::sb3_project_hash(sb3),
+                error: None,
+            });
+            status.diagnostics.clear();
+        }
+        Err(err) => {
+            status.build = Some(BuildStatus {
+                ok: false,
+                timestamp: now,
+                project_hash: None,
+                error: Some(err.to_string()),
+            });
+            status.diagnostics = err.diagnostics.clone();
+        }
+    }
+    status.timestamp = now;
+    write(workspace, &status);
+}
+
+/// Record that a project was sent to the editor. It becomes the editor's
+/// project once the editor acknowledges the load (see record_editor_load).
+pub fn record_sent(workspace: &Path, project_hash: String) {
+    let mut status = STATUS.lock().unwrap();
+    status.pending.push_back(project_hash);
+    while status.pending.len() > MAX_PENDING {
+        status.pending.pop_front();
+    }
+    status.timestamp = now_ms();
+    write(workspace, &status);
+}
+
+/// Record an editor's report on loading the project with `project_hash`
+/// (see sync::confirm_load).
+pub fn record_editor_load(
+    workspace: &Path,
+    ok: bool,
+    error: Option<String>,
+    duration_ms: f64,
+    project_hash: Option<String>,
+) {
+    let now = now_ms();
+    let mut status = STATUS.lock().unwrap();
+    if let Some(hash) = &project_hash {
+        if let Some(i) = status.pending.iter().position(|pending| pending == hash) {
+            status.pending.remove(i);
+        }
+    }
+    if ok {
+        if let Some(hash) = &project_hash {
+            status.editor = Some(EditorStatus {
+                timestamp: now,
+                project_hash: hash.clone(),
+                source: "workspace",
+            });
+        }
+    }
+    status.load = Some(LoadStatus {
+        ok,
+        timestamp: now,
+        duration_ms,
+        project_hash,
+        error,
+    });
+    status.timestamp = now;
+    write(workspace, &status);
+}
+
+/// Record the project the editor now has.
+pub fn record_editor_project(workspace: &Path, project_hash: String, source: &'static str) {
+    let now = now_ms();
+    let mut status = STATUS.lock().unwrap();
+    status.editor = Some(EditorStatus {
+        timestamp: now,
+        project_hash,
+        source,
+    });
+    status.timestamp = now;
+    write(workspace, &status);
+}
+
+/// Record (Some) or clear (None) a conflict between editor and workspace.
+pub fn record_conflict(workspace: &Path, conflict: Option<ConflictStatus>) {
+    let mut status = STATUS.lock().unwrap();
+    status.conflict = conflict;
+    status.timestamp = now_ms();
+    write(workspace, &status);
+}
+
+/// Forget everything recorded so far, e.g. after switching to another
+/// project. The next record_* call writes the new project's status file.
+pub fn reset() {
+    let mut status = STATUS.lock().unwrap();
+    status.build = None;
+    status.diagnostics.clear();
+    status.editor = None;
+    status.load = None;
+    status.pending.clear();
+    status.conflict = None;
+    status.timestamp = now_ms();
+}
+
+pub fn status_path(workspace: &Path) -> PathBuf {
+    workspace.join(STATE_DIR).join(STATUS_FILE)
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Write via a temp file + rename so a polling agent never reads half a file.
+fn write(workspace: &Path, status: &SyncStatus) {
+    let path = status_path(workspace);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).ok();
+    }
+    let json = match serde_json::to_string_pretty(status) {
+        Ok(j) => j,
+        Err(err) => {
+            log::error!("Failed to serialize status: {}", err);
+            return;
+        }
+    };
+    let tmp = path.with_extension("json.tmp");
+    if let Err(err) = fs::write(&tmp, json).and_then(|_| fs::rename(&tmp, &path)) {
+        log::error!("Failed to write {:?}: {}", path, err);
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{mpsc, Mutex, MutexGuard};
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::commands::WorkspacePath;
+use crate::history;
+use crate::journal;
+use crate::merge;
+use crate::patch;
+use crate::projects::{self, ProjectInfo};
+use crate::status::{self, ConflictStatus};
+use crate::watcher;
+use crate::workspace;
+
+/// An editor the project is open in: the app's webview, or a browser
+/// connected to the local server (see server.rs)
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Editor {
+    Webview,
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    Browser(u64),
+}
+
+/// Kinds of sync jobs. Adjacent queued jobs of the same kind (other than
+/// Other) are coalesced: only the newest runs, since it supersedes the rest.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    /// Rebuild and send the workspace after files changed
+    Rebuild,
+    /// Apply a save from an editor (each save holds the whole project, so
+    /// it supersedes earlier ones from the same editor)
+    EditorSave(Editor),
+    /// Anything else; always runs
+    Other,
+}
+
+struct Job {
+    kind: JobKind,
+    run: Box<dyn FnOnce(&AppHandle) + Send>,
+}
+
+/// Queue of the sync worker, the one thread that reads and writes the
+/// workspace (builds, extracts, restores, project switches, ...)
+static QUEUE: Mutex<Option<mpsc::Sender<Job>>> = Mutex::new(None);
+/// Held by the worker while it runs a job
+static BUSY: Mutex<()> = Mutex::new(());
+
+/// Start the sync worker. Jobs run one at a time in the order queued.
+pub fn start_worker(app: AppHandle) {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    *QUEUE.lock().unwrap() = Some(sender);
+    std::thread::spawn(move || {
+        while let Ok(first) = receiver.recv() {
+            let mut jobs = std::iter::once(first).chain(receiver.try_iter()).peekable();
+            while let Some(job) = jobs.next() {
+                let superseded = job.kind != JobKind::Other
+                    && jobs.peek().is_some_and(|next| next.kind == job.kind);
+                if superseded {
+                    // Dropping the job tells its caller (see wait_for)
+                    continue;
+                }
+                let _busy = BUSY.lock().unwrap_or_else(|e| e.into_inner());
+                if panic::catch_unwind(AssertUnwindSafe(|| (job.run)(&app))).is_err() {
+                    log::error!("[live-scratch] sync job panicked");
+                }
+            }
+        }
+    });
+}
+
+/// Queue `f` on the sync worker. The receiver yields its result, or
+/// disconnects without one if a newer job of the same kind superseded it.
+pub fn submit<T, F>(kind: JobKind, f: F) -> mpsc::Receiver<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&AppHandle) -> T + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let job = Job {
+        kind,
+        run: Box::new(move |app| {
+            let _ = sender.send(f(app));
+        }),
+    };
+    match QUEUE.lock().unwrap().as_ref() {
+        Some(queue) => {
+            if queue.send(job).is_err() {
+                log::error!("[live-scratch] sync worker has stopped");
+            }
+        }
+        None => log::error!("[live-scratch] sync worker not started"),
+    }
+    receiver
+}
+
+/// submit and wait for the result; None if the job was superseded.
+pub fn run<T, F>(kind: JobKind, f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&AppHandle) -> T + Send + 'static,
+{
+    submit(kind, f).recv().ok()
+}
+
+/// Wait until the worker is between jobs and keep it there while the guard
+/// lives, e.g. to look at the workspace without seeing half a save.
+pub fn pause_worker() -> MutexGuard<'static, ()> {
+    BUSY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Whether `workspace_path` is still the open project (queued jobs for a
+/// project that was switched away from are dropped).
+pub fn is_current(app: &AppHandle, workspace_path: &Path) -> bool {
+    app.state::<WorkspacePath>().get() == workspace_path
+}
+
+/// What the backend knows about one editor's project
+#[derive(Default)]
+struct EditorState {
+    /// The last project the editor confirmed having: loaded it (see
+    /// confirm_load) or saved it. Base of the three-way merge of its saves
+    /// in apply_editor_save.
+    confirmed: Option<Value>,
+    /// Hashes of the SB3s it fetched and hasn't reported loading yet,
+    /// oldest first
+    fetched: VecDeque<String>,
+}
+
+static EDITOR_STATES: Mutex<Option<HashMap<Editor, EditorState>>> = Mutex::new(None);
+/// The project every editor has, if they all have the same one. Updates
+/// are sent as a patch against it when possible.
+static EDITORS: Mutex<Option<Value>> = Mutex::new(None);
+/// Projects recently sent to the editors with their hashes, newest last,
+/// so a load report can name the project it loaded
+static RECENT: Mutex<VecDeque<(String, Value)>> = Mutex::new(VecDeque::new());
+/// Forget sent projects beyond this (e.g. ones no editor reports loading)
+const MAX_RECENT: usize = 16;
+
+/// The SB3 last sent to the editors and the hash of its project, fetched
+/// by them on `sb3-updated`
+static SENT: Mutex<Option<(Vec<u8>, Option<String>)>> = Mutex::new(None);
+
+fn with_editor<T>(editor: Editor, f: impl FnOnce(&mut EditorState) -> T) -> T {
+    let mut states = EDITOR_STATES.lock().unwrap();
+    f(states
+        .get_or_insert_with(HashMap::new)
+        .entry(editor)
+        .or_default())
+}
+
+/// Record the SB3 built for `editor` by get_project_sb3 as the last one
+/// sent.
+pub fn set_sent(editor: Editor, sb3: &[u8]) {
+    let project = workspace::sb3_project(sb3);
+    let hash = set_synced(sb3, project);
+    record_fetch(editor, hash);
+}
+
+/// Make `sb3` the last SB3 sent and every editor's project. Returns the
+/// hash of its project.
+fn set_synced(sb3: &[u8], project: Option<Value>) -> Option<String> {
+    let hash = project.as_ref().map(workspace::project_hash);
+    *SENT.lock().unwrap() = Some((sb3.to_vec(), hash.clone()));
+    *EDITORS.lock().unwrap() = project.clone();
+    if let (Some(hash), Some(project)) = (&hash, project) {
+        let mut recent = RECENT.lock().unwrap();
+        recent.push_back((hash.clone(), project));
+        while recent.len() > MAX_RECENT {
+            recent.pop_front();
+        }
+    }
+    hash
+}
+
+fn record_fetch(editor: Editor, hash: Option<String>) {
+    if let Some(hash) = hash {
+        with_editor(editor, |state| {
+            state.fetched.push_back(hash);
+            while state.fetched.len() > MAX_RECENT {
+                state.fetched.pop_front();
+            }
+        });
+    }
+}
+
+/// The SB3 last sent to the editors, fetched by `editor` (see
+/// get_updated_sb3).
+pub fn fetch_sent(editor: Editor) -> Option<Vec<u8>> {
+    let (sb3, hash) = SENT.lock().unwrap().clone()?;
+    record_fetch(editor, hash);
+    Some(sb3)
+}
+
+/// Handle `editor`'s report on loading a project: the one with
+/// `project_hash` (a patched project) or else the oldest SB3 it fetched.
+/// Once loaded, that project is the base its saves are merged against.
+/// Returns the hash of the project.
+pub fn confirm_load(editor: Editor, ok: bool, project_hash: Option<String>) -> Option<String> {
+    with_editor(editor, |state| {
+        let hash = project_hash.or_else(|| state.fetched.pop_front())?;
+        if ok {
+            let recent = RECENT.lock().unwrap();
+            if let Some((_, project)) = recent.iter().rev().find(|(h, _)| *h == hash) {
+                state.confirmed = Some(project.clone());
+            }
+        }
+        Some(hash)
+    })
+}
+
+/// Send an event to every editor.
+pub fn emit<S: Serialize + Clone>(app: &AppHandle, event: &str, payload: S) {
+    emit_except(app, None, event, payload);
+}
+
+/// Send an event to every editor except `except`.
+pub fn emit_except<S: Serialize + Clone>(
+    app: &AppHandle,
+    except: Option<Editor>,
+    event: &str,
+    payload: S,
+) {
+    if except != Some(Editor::Webview) {
+        if let Err(err) = app.emit(event, payload.clone()) {
+            log::error!("Failed to emit {}: {}", event, err);
+        }
+    }
+    #[cfg(feature = "server")]
+    crate::server::broadcast(event, &payload, except);
+}
+
+/// Build the workspace and push it to the editor, followed by
+/// `build-succeeded`. Changes to blocks, variables and comments only are
+/// sent as a `project-patch` the editor applies in place; anything else
+/// (assets, sprites, ...) takes a full reload, announced with `sb3-updated`
+/// (the editor fetches the SB3 with get_updated_sb3). If the build
+/// is rejected the editor gets a `build-failed` event carrying the error
+/// and its location instead. Both outcomes are recorded in
+/// .live-scratch/status.json, and each successful build is kept as a
+/// "workspace" snapshot. Returns the SB3 size on success.
+pub fn build_and_emit(app: &AppHandle, workspace_path: &Path) -> Option<usize> {
+    let result = workspace::build_sb3(workspace_path);
+    status::record_build(workspace_path, &result);
+    match result {
+        Ok(sb3) => {
+            // Every state the workspace was built in can be restored, e.g.
+            // after a bad agent edit (unchanged rebuilds are skipped)
+            history::record(workspace_path, &sb3, "workspace");
+            let project = workspace::sb3_project(&sb3);
+            let patch = match (EDITORS.lock().unwrap().as_ref(), &project) {
+                (Some(old), Some(new)) => patch::diff(old, new),
+                _ => None,
+            };
+            // Before emitting, so editors fetching it get this one
+            let hash = set_synced(&sb3, project);
+            let sent = match patch {
+                Some(patch) if patch.is_empty() => {
+                    log::info!("[live-scratch] editor already up to date");
+                    false
+                }
+                Some(mut patch) => {
+                    // Reported back by the editors once applied
+                    patch.hash = hash.clone().unwrap_or_default();
+                    emit(app, "project-patch", &patch);
+                    log::info!(
+                        "[live-scratch] emitted project-patch ({} target(s))",
+                        patch.targets.len()
+                    );
+                    true
+                }
+                None => {
+                    emit(app, "sb3-updated", ());
+                    log::info!("[live-scratch] emitted sb3-updated ({} bytes)", sb3.len());
+                    true
+                }
+            };
+            emit(app, "build-succeeded", ());
+            if let (true, Some(hash)) = (sent, hash) {
+                status::record_sent(workspace_path, hash);
+            }
+            Some(sb3.len())
+        }
+        Err(err) => {
+            log::warn!("[live-scratch] skipping emit (build error): {}", err);
+            emit(app, "build-failed", &err);
+            None
+        }
+    }
+}
+
+/// Apply a save from `editor` to the workspace. Once applied, it is sent
+/// on to the other editors.
+///
+/// If the workspace was edited too since the editor last loaded or saved a
+/// project (the project it confirmed, not merely the last one sent, which
+/// it may have saved over instead of loading), the two versions
+/// are merged block by block, written to the workspace and sent back to the
+/// editor. If they can't be merged the workspace keeps its version, the
+/// editor's is kept as a "conflict" snapshot, and the conflict is reported
+/// in status.json and to the editor as `sync-conflict`.
+pub fn apply_editor_save(
+    app: &AppHandle,
+    workspace_path: &Path,
+    data: &[u8],
+    editor: Editor,
+) -> Result<(), String> {
+    let theirs = workspace::sb3_project(data)
+        .ok_or("The editor sent an SB3 without a valid project.json")?;
+    let base = with_editor(editor, |state| state.confirmed.clone());
+    let ours = workspace::current_project(workspace_path);
+
+    let merged = match (&base, ours) {
+        // Nothing new from the editor; bring it up to date if the
+        // workspace moved on
+        (Some(base), ours) if theirs == *base => {
+            if ours.as_ref().ok() != Some(base) {
+                build_and_emit(app, workspace_path);
+            }
+            return Ok(());
+        }
+        (Some(base), Ok(ours)) if ours != *base => Some(merge::merge_projects(base, &ours, &theirs)),
+        // A workspace that no longer loads was edited since the last sync
+        (Some(_), Err(err)) => Some(Err(vec![format!("workspace project: {}", err)])),
+        _ => None,
+    };
+
+    match merged {
+        None => {
+            workspace::extract_sb3(workspace_path, data)?;
+            let hash = workspace::project_hash(&theirs);
+            status::record_editor_project(workspace_path, hash, "editor");
+            history::record(workspace_path, data, "editor");
+            status::record_conflict(workspace_path, None);
+            set_synced(data, Some(theirs.clone()));
+            with_editor(editor, |state| state.confirmed = Some(theirs));
+            emit_except(app, Some(editor), "sb3-updated", ());
+        }
+        Some(Ok(project)) => {
+            log::info!("[live-scratch] merged editor save with workspace edits");
+            let merged_sb3 = workspace::replace_sb3_project(data, &project)?;
+            workspace::extract_sb3(workspace_path, &merged_sb3)?;
+            history::record(workspace_path, &merged_sb3, "merge");
+            status::record_conflict(workspace_path, None);
+            // Only the editor that saved has its side of the merge, so the
+            // result can't be sent as a patch, and its next save is merged
+            // against its own version until it loads the result
+            *EDITORS.lock().unwrap() = None;
+            with_editor(editor, |state| state.confirmed = Some(theirs));
+            // The editor doesn't have the workspace side of the merge yet
+            build_and_emit(app, workspace_path);
+        }
+        Some(Err(conflicts)) => {
+            log::warn!(
+                "[live-scratch] editor save conflicts with workspace edits, keeping both: {}",
+                conflicts.join(", ")
+            );
+            let conflict = ConflictStatus {
+                timestamp: status::now_ms(),
+                snapshot: history::record(workspace_path, data, "conflict"),
+                conflicts,
+            };
+            status::record_conflict(workspace_path, Some(conflict.clone()));
+            *EDITORS.lock().unwrap() = None;
+            emit(app, "sync-conflict", &conflict);
+        }
+    }
+    Ok(())
+}
+
+/// Make `name` the current project: create it from the default project if
+/// needed, point the watcher at it, remember it for the next launch and
+/// load it into the editor. The editor is told with `project-switched`.
+pub fn switch_project(app: &AppHandle, name: &str) -> Result<(), String> {
+    let state = app.state::<WorkspacePath>();
+    let dir = projects::project_dir(&state.root, name)?;
+    if state.project_name() == name {
+        return Ok(());
+    }
+
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    journal::recover(&dir);
+    workspace::ensure_default_project(&dir, &state.resource_dir);
+    workspace::ensure_claude_md(&dir, &state.resource_dir);
+
+    state.set(name.to_string(), dir.clone());
+    status::reset();
+    *SENT.lock().unwrap() = None;
+    *EDITORS.lock().unwrap() = None;
+    *EDITOR_STATES.lock().unwrap() = None;
+    RECENT.lock().unwrap().clear();
+    watcher::start_watcher(dir.clone());
+    projects::remember_project(name);
+    log::info!("[live-scratch] switched to project {:?} ({:?})", name, dir);
+
+    let info = ProjectInfo {
+        name: name.to_string(),
+        path: dir.to_string_lossy().to_string(),
+        current: true,
+    };
+    emit(app, "project-switched", &info);
+    build_and_emit(app, &dir);
+    if let Err(err) = crate::set_app_menu(app) {
+        log::error!("Failed to rebuild menu: {}", err);
+    }
+    Ok(())
+}
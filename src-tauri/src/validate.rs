@@ -0,0 +1,638 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::assets;
+use crate::layout::ProjectSource;
+
+/// A problem found in a project file, located by JSON pointer and, when the
+/// value could be found in the file text, by line and column (1-based).
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub pointer: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(file: &str, pointer: &str, message: &str) -> Self {
+        Diagnostic {
+            file: file.to_string(),
+            pointer: pointer.to_string(),
+            line: None,
+            column: None,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        if !self.pointer.is_empty() {
+            write!(f, " {}", self.pointer)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Validate the project against the SB3 schema, check that the files of
+/// its costumes and sounds exist, and locate every problem in the
+/// workspace file it came from.
+pub fn check(workspace: &Path, source: &ProjectSource) -> Vec<Diagnostic> {
+    let mut texts: HashMap<String, Option<String>> = HashMap::new();
+    let mut errors = validate_project(&source.project);
+    errors.extend(assets::missing_assets(workspace, &source.project));
+    errors
+        .into_iter()
+        .map(|(pointer, message)| {
+            let (file, local) = source.locate(&pointer);
+            let text = texts
+                .entry(file.clone())
+                .or_insert_with(|| fs::read_to_string(workspace.join(&file)).ok());
+            let position = text.as_deref().and_then(|t| locate_nearest(t, &local));
+            Diagnostic {
+                file,
+                pointer: local,
+                line: position.map(|(line, _)| line),
+                column: position.map(|(_, column)| column),
+                message,
+            }
+        })
+        .collect()
+}
+
+/// Structural check of an assembled project.json: targets, blocks,
+/// variables, costumes, sounds and monitors. Returns (JSON pointer, message)
+/// pairs; missing properties are reported on the object that lacks them.
+pub fn validate_project(project: &Value) -> Vec<(String, String)> {
+    let mut v = Validator::default();
+
+    let Some(root) = project.as_object() else {
+        v.error("", "project must be a JSON object");
+        return v.errors;
+    };
+
+    match root.get("targets") {
+        Some(Value::Array(targets)) => {
+            if targets.is_empty() {
+                v.error("/targets", "project has no targets");
+            }
+            let stages = targets
+                .iter()
+                .filter(|t| t.get("isStage") == Some(&Value::Bool(true)))
+                .count();
+            if !targets.is_empty() && stages != 1 {
+                v.error(
+                    "/targets",
+                    &format!("project must have exactly one stage (found {})", stages),
+                );
+            }
+            let mut names = HashSet::new();
+            for (i, target) in targets.iter().enumerate() {
+                let ptr = format!("/targets/{}", i);
+                v.target(&ptr, target);
+                if let Some(name) = target.get("name").and_then(Value::as_str) {
+                    if !names.insert(name) {
+                        v.error(&ptr, &format!("duplicate target name \"{}\"", name));
+                    }
+                }
+            }
+        }
+        Some(_) => v.error("/targets", "must be an array"),
+        None => v.error("", "missing \"targets\""),
+    }
+
+    match root.get("monitors") {
+        Some(Value::Array(monitors)) => {
+            for (i, monitor) in monitors.iter().enumerate() {
+                v.monitor(&format!("/monitors/{}", i), monitor);
+            }
+        }
+        Some(_) => v.error("/monitors", "must be an array"),
+        None => {}
+    }
+
+    match root.get("extensions") {
+        Some(Value::Array(extensions)) => {
+            for (i, extension) in extensions.iter().enumerate() {
+                if !extension.is_string() {
+                    v.error(&format!("/extensions/{}", i), "must be a string");
+                }
+            }
+        }
+        Some(_) => v.error("/extensions", "must be an array"),
+        None => {}
+    }
+
+    v.errors
+}
+
+#[derive(Default)]
+struct Validator {
+    errors: Vec<(String, String)>,
+}
+
+impl Validator {
+    fn error(&mut self, pointer: &str, message: &str) {
+        self.errors.push((pointer.to_string(), message.to_string()));
+    }
+
+    /// Check that `key` exists on `obj` and satisfies `is_ok`.
+    fn field<F>(&mut self, ptr: &str, obj: &Map<String, Value>, key: &str, kind: &str, is_ok: F)
+    where
+        F: Fn(&Value) -> bool,
+    {
+        match obj.get(key) {
+            Some(value) if is_ok(value) => {}
+            Some(_) => self.error(&child(ptr, key), &format!("must be {}", kind)),
+            None => self.error(ptr, &format!("missing \"{}\"", key)),
+        }
+    }
+
+    /// Like `field`, but the key may be absent.
+    fn optional<F>(&mut self, ptr: &str, obj: &Map<String, Value>, key: &str, kind: &str, is_ok: F)
+    where
+        F: Fn(&Value) -> bool,
+    {
+        if obj.contains_key(key) {
+            self.field(ptr, obj, key, kind, is_ok);
+        }
+    }
+
+    fn target(&mut self, ptr: &str, target: &Value) {
+        let Some(obj) = target.as_object() else {
+            self.error(ptr, "target must be an object");
+            return;
+        };
+
+        self.field(ptr, obj, "name", "a non-empty string", |v| {
+            v.as_str().is_some_and(|s| !s.is_empty())
+        });
+        self.field(ptr, obj, "isStage", "a boolean", Value::is_boolean);
+
+        self.optional(ptr, obj, "currentCostume", "a costume index", Value::is_u64);
+        self.variables(ptr, obj);
+
+        for key in ["broadcasts", "comments"] {
+            self.optional(ptr, obj, key, "an object", Value::is_object);
+        }
+        if let Some(broadcasts) = obj.get("broadcasts").and_then(Value::as_object) {
+            for (id, name) in broadcasts {
+                if !name.is_string() {
+                    self.error(&child(&child(ptr, "broadcasts"), id), "must be a string");
+                }
+            }
+        }
+
+        match obj.get("blocks") {
+            Some(Value::Object(blocks)) => self.blocks(&child(ptr, "blocks"), blocks),
+            Some(_) => self.error(&child(ptr, "blocks"), "must be an object"),
+            None => {}
+        }
+
+        match obj.get("costumes") {
+            Some(Value::Array(costumes)) => {
+                if costumes.is_empty() {
+                    self.error(&child(ptr, "costumes"), "target must have at least one costume");
+                }
+                for (i, costume) in costumes.iter().enumerate() {
+                    self.asset(&format!("{}/costumes/{}", ptr, i), costume, "costume");
+                }
+            }
+            Some(_) => self.error(&child(ptr, "costumes"), "must be an array"),
+            None => self.error(ptr, "missing \"costumes\""),
+        }
+
+        match obj.get("sounds") {
+            Some(Value::Array(sounds)) => {
+                for (i, sound) in sounds.iter().enumerate() {
+                    self.asset(&format!("{}/sounds/{}", ptr, i), sound, "sound");
+                }
+            }
+            Some(_) => self.error(&child(ptr, "sounds"), "must be an array"),
+            None => self.error(ptr, "missing \"sounds\""),
+        }
+    }
+
+    fn variables(&mut self, ptr: &str, obj: &Map<String, Value>) {
+        for key in ["variables", "lists"] {
+            let list_ptr = child(ptr, key);
+            let entries = match obj.get(key) {
+                Some(Value::Object(entries)) => entries,
+                Some(_) => {
+                    self.error(&list_ptr, "must be an object");
+                    continue;
+                }
+                None => continue,
+            };
+            for (id, entry) in entries {
+                let entry_ptr = child(&list_ptr, id);
+                let ok = match entry.as_array() {
+                    Some(items) if items.len() >= 2 && items[0].is_string() => {
+                        key == "variables" || items[1].is_array()
+                    }
+                    _ => false,
+                };
+                if !ok {
+                    let shape = if key == "variables" {
+                        "[name, value]"
+                    } else {
+                        "[name, [items]]"
+                    };
+                    self.error(&entry_ptr, &format!("must be {}", shape));
+                }
+            }
+        }
+    }
+
+    fn asset(&mut self, ptr: &str, asset: &Value, kind: &str) {
+        let Some(obj) = asset.as_object() else {
+            self.error(ptr, &format!("{} must be an object", kind));
+            return;
+        };
+        for key in ["name", "assetId", "dataFormat"] {
+            self.field(ptr, obj, key, "a string", Value::is_string);
+        }
+        self.optional(ptr, obj, "md5ext", "a string", Value::is_string);
+        if kind == "costume" {
+            for key in ["rotationCenterX", "rotationCenterY", "bitmapResolution"] {
+                self.optional(ptr, obj, key, "a number", Value::is_number);
+            }
+        }
+    }
+
+    fn blocks(&mut self, ptr: &str, blocks: &Map<String, Value>) {
+        let exists = |id: &Value| id.as_str().is_some_and(|id| blocks.contains_key(id));
+
+        for (id, block) in blocks {
+            let block_ptr = child(ptr, id);
+
+            // Top-level variable/list reporters are stored as primitive arrays
+            if let Some(items) = block.as_array() {
+                if items.len() < 3 || !items[0].is_number() {
+                    self.error(&block_ptr, "must be a block object or [type, name, id, x, y]");
+                }
+                continue;
+            }
+            let Some(obj) = block.as_object() else {
+                self.error(&block_ptr, "block must be an object");
+                continue;
+            };
+
+            self.field(&block_ptr, obj, "opcode", "a string", Value::is_string);
+            self.optional(&block_ptr, obj, "topLevel", "a boolean", Value::is_boolean);
+            self.optional(&block_ptr, obj, "shadow", "a boolean", Value::is_boolean);
+
+            for key in ["next", "parent"] {
+                match obj.get(key) {
+                    None | Some(Value::Null) => {}
+                    Some(target) if exists(target) => {}
+                    Some(Value::String(target)) => self.error(
+                        &child(&block_ptr, key),
+                        &format!("{} block \"{}\" does not exist", key, target),
+                    ),
+                    Some(_) => self.error(&child(&block_ptr, key), "must be a block id or null"),
+                }
+            }
+
+            match obj.get("inputs") {
+                Some(Value::Object(inputs)) => {
+                    for (name, input) in inputs {
+                        self.input(&child(&child(&block_ptr, "inputs"), name), input, &exists);
+                    }
+                }
+                Some(_) => self.error(&child(&block_ptr, "inputs"), "must be an object"),
+                None => {}
+            }
+
+            match obj.get("fields") {
+                Some(Value::Object(fields)) => {
+                    for (name, field) in fields {
+                        if !matches!(field.as_array(), Some(f) if !f.is_empty()) {
+                            self.error(
+                                &child(&child(&block_ptr, "fields"), name),
+                                "must be [value] or [value, id]",
+                            );
+                        }
+                    }
+                }
+                Some(_) => self.error(&child(&block_ptr, "fields"), "must be an object"),
+                None => {}
+            }
+        }
+    }
+
+    /// Inputs are [shadowType, block, shadow?] where block/shadow are a
+    /// block id, an inline primitive array or null.
+    fn input<F>(&mut self, ptr: &str, input: &Value, exists: &F)
+    where
+        F: Fn(&Value) -> bool,
+    {
+        let Some(items) = input.as_array() else {
+            self.error(ptr, "input must be an array");
+            return;
+        };
+        if items.len() < 2 || !items[0].as_u64().is_some_and(|t| (1..=3).contains(&t)) {
+            self.error(ptr, "input must be [shadowType (1-3), block, shadow?]");
+            return;
+        }
+        for (i, item) in items.iter().enumerate().skip(1) {
+            match item {
+                Value::Null | Value::Array(_) => {}
+                Value::String(id) if !exists(item) => self.error(
+                    &format!("{}/{}", ptr, i),
+                    &format!("input block \"{}\" does not exist", id),
+                ),
+                Value::String(_) => {}
+                _ => self.error(
+                    &format!("{}/{}", ptr, i),
+                    "must be a block id, a primitive array or null",
+                ),
+            }
+        }
+    }
+
+    fn monitor(&mut self, ptr: &str, monitor: &Value) {
+        let Some(obj) = monitor.as_object() else {
+            self.error(ptr, "monitor must be an object");
+            return;
+        };
+        for key in ["id", "opcode", "mode"] {
+            self.field(ptr, obj, key, "a string", Value::is_string);
+        }
+        self.field(ptr, obj, "params", "an object", Value::is_object);
+        self.optional(ptr, obj, "spriteName", "a string or null", |v| {
+            v.is_string() || v.is_null()
+        });
+    }
+}
+
+/// Append an escaped reference token to a JSON pointer (RFC 6901).
+fn child(pointer: &str, key: &str) -> String {
+    format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Line and column of the value at `pointer`, or of its closest existing
+/// ancestor if the pointer does not resolve.
+fn locate_nearest(text: &str, pointer: &str) -> Option<(usize, usize)> {
+    let mut pointer = pointer;
+    loop {
+        if let Some(position) = locate(text, pointer) {
+            return Some(position);
+        }
+        pointer = &pointer[..pointer.rfind('/')?];
+    }
+}
+
+/// Line and column (1-based) of the value at `pointer` in JSON text.
+pub fn locate(text: &str, pointer: &str) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut pos = skip_ws(bytes, 0);
+
+    if !pointer.is_empty() {
+        for token in pointer.strip_prefix('/')?.split('/') {
+            let token = token.replace("~1", "/").replace("~0", "~");
+            pos = match bytes.get(pos)? {
+                b'{' => find_member(text, pos, &token)?,
+                b'[' => find_element(bytes, pos, token.parse().ok()?)?,
+                _ => return None,
+            };
+        }
+    }
+
+    let before = &text[..pos];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    Some((line, column))
+}
+
+/// Position of the value of member `key` in the object starting at `pos`.
+fn find_member(text: &str, pos: usize, key: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut pos = skip_ws(bytes, pos + 1);
+    while bytes.get(pos)? == &b'"' {
+        let end = skip_string(bytes, pos)?;
+        let name: String = serde_json::from_str(&text[pos..end]).ok()?;
+        pos = skip_ws(bytes, end);
+        if bytes.get(pos)? != &b':' {
+            return None;
+        }
+        pos = skip_ws(bytes, pos + 1);
+        if name == key {
+            return Some(pos);
+        }
+        pos = skip_ws(bytes, skip_value(bytes, pos)?);
+        if bytes.get(pos)? != &b',' {
+            return None;
+        }
+        pos = skip_ws(bytes, pos + 1);
+    }
+    None
+}
+
+/// Position of element `index` in the array starting at `pos`.
+fn find_element(bytes: &[u8], pos: usize, index: usize) -> Option<usize> {
+    let mut pos = skip_ws(bytes, pos + 1);
+    if bytes.get(pos)? == &b']' {
+        return None;
+    }
+    for _ in 0..index {
+        pos = skip_ws(bytes, skip_value(bytes, pos)?);
+        if bytes.get(pos)? != &b',' {
+            return None;
+        }
+        pos = skip_ws(bytes, pos + 1);
+    }
+    Some(pos)
+}
+
+fn skip_ws(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+/// Position just past the string starting at `pos`.
+fn skip_string(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    pos += 1;
+    loop {
+        match bytes.get(pos)? {
+            b'\\' => pos += 2,
+            b'"' => return Some(pos + 1),
+            _ => pos += 1,
+        }
+    }
+}
+
+/// Position just past the value starting at `pos`.
+fn skip_value(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    match bytes.get(pos)? {
+        b'"' => skip_string(bytes, pos),
+        b'{' | b'[' => {
+            let mut depth = 0usize;
+            loop {
+                match bytes.get(pos)? {
+                    b'"' => {
+                        pos = skip_string(bytes, pos)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(pos + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                pos += 1;
+            }
+        }
+        _ => {
+            while pos < bytes.len() && !matches!(bytes[pos], b',' | b'}' | b']')
+                && !bytes[pos].is_ascii_whitespace()
+            {
+                pos += 1;
+            }
+            Some(pos)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A valid project whose sprite has `blocks`.
+    fn project(blocks: Value) -> Value {
+        let costume = json!({ "name": "costume1", "assetId": "a", "dataFormat": "svg" });
+        json!({
+            "targets": [
+                { "isStage": true, "name": "Stage", "costumes": [costume], "sounds": [] },
+                {
+                    "isStage": false,
+                    "name": "Sprite1",
+                    "blocks": blocks,
+                    "costumes": [costume],
+                    "sounds": []
+                }
+            ],
+            "monitors": [],
+            "extensions": []
+        })
+    }
+
+    fn pointers(errors: &[(String, String)]) -> Vec<&str> {
+        errors.iter().map(|(pointer, _)| pointer.as_str()).collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_project() {
+        let blocks = json!({
+            "a": { "opcode": "event_whenflagclicked", "next": "b", "topLevel": true },
+            "b": {
+                "opcode": "motion_movesteps",
+                "parent": "a",
+                "inputs": { "STEPS": [1, [4, "10"]] },
+                "fields": {}
+            },
+            "c": [12, "my variable", "var-id", 0, 0]
+        });
+        assert_eq!(validate_project(&project(blocks)), vec![]);
+    }
+
+    #[test]
+    fn reports_dangling_block_ids() {
+        let blocks = json!({
+            "a": {
+                "opcode": "control_if",
+                "next": "gone",
+                "parent": "missing",
+                "inputs": { "CONDITION": [2, "nowhere"], "SUBSTACK": [3, null, "lost"] }
+            }
+        });
+        let errors = validate_project(&project(blocks));
+        assert_eq!(
+            pointers(&errors),
+            [
+                "/targets/1/blocks/a/next",
+                "/targets/1/blocks/a/parent",
+                "/targets/1/blocks/a/inputs/CONDITION/1",
+                "/targets/1/blocks/a/inputs/SUBSTACK/2",
+            ]
+        );
+        assert_eq!(errors[0].1, "next block \"gone\" does not exist");
+        assert_eq!(errors[2].1, "input block \"nowhere\" does not exist");
+    }
+
+    #[test]
+    fn reports_missing_fields_on_the_object_lacking_them() {
+        let mut project = project(json!({ "a": { "next": null } }));
+        let sprite = project["targets"][1].as_object_mut().unwrap();
+        sprite.remove("name");
+        sprite.remove("sounds");
+        let errors = validate_project(&project);
+        assert_eq!(
+            errors,
+            [
+                ("/targets/1".to_string(), "missing \"name\"".to_string()),
+                (
+                    "/targets/1/blocks/a".to_string(),
+                    "missing \"opcode\"".to_string()
+                ),
+                ("/targets/1".to_string(), "missing \"sounds\"".to_string()),
+            ]
+        );
+
+        let errors = validate_project(&json!({}));
+        assert_eq!(errors, [(String::new(), "missing \"targets\"".to_string())]);
+    }
+
+    #[test]
+    fn reports_targets_that_are_not_objects() {
+        let mut project = project(json!({}));
+        project["targets"][1] = json!("Sprite1");
+        let errors = validate_project(&project);
+        assert!(errors.contains(&(
+            "/targets/1".to_string(),
+            "target must be an object".to_string()
+        )));
+        assert!(validate_project(&json!({ "targets": {} }))
+            .contains(&("/targets".to_string(), "must be an array".to_string())));
+    }
+
+    /// Braces, brackets and escaped quotes inside strings, and keys that
+    /// need escaping in a JSON pointer.
+    const TEXT: &str = "{\n  \"s\": \"}{\\\"[\",\n  \"a/b\": {\"~x\": [1, {\"u\": true}]}\n}";
+
+    #[test]
+    fn locate_finds_nested_values() {
+        assert_eq!(locate(TEXT, ""), Some((1, 1)));
+        assert_eq!(locate(TEXT, "/s"), Some((2, 8)));
+        assert_eq!(locate(TEXT, "/a~1b"), Some((3, 10)));
+        assert_eq!(locate(TEXT, "/a~1b/~0x"), Some((3, 17)));
+        assert_eq!(locate(TEXT, "/a~1b/~0x/1"), Some((3, 21)));
+        assert_eq!(locate(TEXT, "/a~1b/~0x/1/u"), Some((3, 27)));
+    }
+
+    #[test]
+    fn locate_fails_on_missing_values_and_nearest_falls_back() {
+        assert_eq!(locate(TEXT, "/t"), None);
+        assert_eq!(locate(TEXT, "/a~1b/~0x/5"), None);
+        assert_eq!(locate(TEXT, "/s/0"), None);
+        assert_eq!(locate_nearest(TEXT, "/a~1b/~0x/5/u"), Some((3, 17)));
+        assert_eq!(locate_nearest(TEXT, "/t"), Some((1, 1)));
+    }
+}
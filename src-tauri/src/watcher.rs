@@ -1,86 +1,93 @@
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::time::Duration;
 
-use notify::RecursiveMode;
-use notify_debouncer_full::{new_debouncer, DebounceEventResult};
-use tauri::{AppHandle, Emitter};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, Debouncer, RecommendedCache};
 
-use crate::workspace;
+use crate::assets;
+use crate::echo;
+use crate::ignore_rules::IgnoreRules;
+use crate::layout;
+use crate::sync::{self, JobKind};
 
-/// Flag to prevent file-change → rebuild → emit loop when we ourselves write to workspace
-static IGNORE_FILE_CHANGE: AtomicBool = AtomicBool::new(false);
+/// The running watcher; replacing it (or setting it to None) stops the
+/// previous one.
+static WATCHER: Mutex<Option<Debouncer<RecommendedWatcher, RecommendedCache>>> =
+    Mutex::new(None);
 
-pub fn set_ignore(val: bool) {
-    IGNORE_FILE_CHANGE.store(val, Ordering::SeqCst);
-}
-
-pub fn is_ignoring() -> bool {
-    IGNORE_FILE_CHANGE.load(Ordering::SeqCst)
-}
-
-/// Start watching the workspace directory. On change, build SB3 and emit to frontend.
-pub fn start_watcher(app: AppHandle, workspace_path: PathBuf) {
-    std::thread::spawn(move || {
-        let app_handle = app.clone();
-        let ws_path = workspace_path.clone();
+/// Start watching the workspace directory, replacing any previous watcher
+/// (e.g. after switching projects). On change, queue a rebuild on the sync
+/// worker, which sends the SB3 to the frontend (or reports the build error).
+pub fn start_watcher(workspace_path: PathBuf) {
+    let mut current = WATCHER.lock().unwrap();
+    // Stop the old watcher first so it can't rebuild the previous project
+    current.take();
 
-        let mut debouncer = new_debouncer(
-            Duration::from_millis(300),
-            None,
-            move |result: DebounceEventResult| {
-                if is_ignoring() {
-                    return;
-                }
-
-                match result {
-                    Ok(events) => {
-                        // Ignore changes to non-Scratch files (e.g. CLAUDE.md)
-                        let has_scratch_change = events.iter().any(|e| {
-                            e.paths.iter().any(|p| {
-                                p.extension().map_or(true, |ext| ext != "md")
+    let ws_path = workspace_path.clone();
+    let debouncer = new_debouncer(
+        Duration::from_millis(300),
+        None,
+        move |result: DebounceEventResult| {
+            match result {
+                Ok(events) => {
+                    // Ignore reads (reported on Linux, e.g. by our own
+                    // builds), changes that can't affect the build (see
+                    // layout::is_project_change) and files still as the
+                    // backend wrote them (echoes of editor saves, SB3 opens,
+                    // etc.)
+                    // Look at the files between sync jobs, so a write in
+                    // progress isn't mistaken for an edit
+                    let paused = sync::pause_worker();
+                    let ignore = IgnoreRules::load(&ws_path);
+                    let has_scratch_change = events.iter().any(|e| {
+                        !matches!(e.kind, EventKind::Access(_))
+                            && e.paths.iter().any(|p| {
+                                layout::is_project_change(&ws_path, &ignore, p) && !echo::is_echo(p)
                             })
-                        });
-                        if events.is_empty() || !has_scratch_change {
+                    });
+                    drop(paused);
+                    if events.is_empty() || !has_scratch_change {
+                        return;
+                    }
+                    log::info!("[live-scratch] file change detected");
+
+                    let ws_path = ws_path.clone();
+                    sync::submit(JobKind::Rebuild, move |app| {
+                        if !sync::is_current(app, &ws_path) {
                             return;
                         }
-                        log::info!("[live-scratch] file change detected");
+                        // Hash-named assets edited in place get renamed to
+                        // their new MD5 (our own writes, so they don't trigger
+                        // another round). A broken project.json is reported
+                        // by the build below.
+                        let _ = assets::rehash_edited_assets(&ws_path);
 
-                        match workspace::build_sb3(&ws_path) {
-                            Some(sb3) => {
-                                let encoded = base64::Engine::encode(
-                                    &base64::engine::general_purpose::STANDARD,
-                                    &sb3,
-                                );
-                                if let Err(err) = app_handle.emit("sb3-updated", &encoded) {
-                                    log::error!("Failed to emit sb3-updated: {}", err);
-                                }
-                                log::info!("[live-scratch] emitted sb3-updated ({} bytes)", sb3.len());
-                            }
-                            None => {
-                                log::warn!("[live-scratch] skipping emit (build error)");
-                            }
-                        }
-                    }
-                    Err(errors) => {
-                        for err in errors {
-                            log::error!("Watch error: {}", err);
-                        }
+                        sync::build_and_emit(app, &ws_path);
+                    });
+                }
+                Err(errors) => {
+                    for err in errors {
+                        log::error!("Watch error: {}", err);
                     }
                 }
-            },
-        )
-        .expect("Failed to create file watcher");
-
-        debouncer
-            .watch(&workspace_path, RecursiveMode::NonRecursive)
-            .expect("Failed to watch workspace directory");
+            }
+        },
+    );
+    let mut debouncer = match debouncer {
+        Ok(d) => d,
+        Err(err) => {
+            log::error!("Failed to create file watcher: {}", err);
+            return;
+        }
+    };
 
-        log::info!("[live-scratch] watching {:?}", workspace_path);
+    // Recursive so edits to targets/*.json (split layout) are picked up
+    if let Err(err) = debouncer.watch(&workspace_path, RecursiveMode::Recursive) {
+        log::error!("Failed to watch {:?}: {}", workspace_path, err);
+        return;
+    }
 
-        // Keep the watcher alive
-        loop {
-            std::thread::sleep(Duration::from_secs(60));
-        }
-    });
+    log::info!("[live-scratch] watching {:?}", workspace_path);
+    *current = Some(debouncer);
 }
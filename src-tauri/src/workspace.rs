@@ -1,12 +1,33 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::io::{Read as _, Write as _};
-use std::path::Path;
+use std::io::{Read, Seek, Write as _};
+use std::path::{Component, Path, PathBuf};
 
-/// Copy default project files into workspace if project.json doesn't exist
+use serde::Serialize;
+
+use crate::assets;
+use crate::backup;
+use crate::export;
+use crate::journal::Transaction;
+use crate::layout;
+use crate::status;
+use crate::validate;
+
+/// Limits on the SB3 files we extract, so a malicious or corrupt file can't
+/// exhaust memory or disk. Real projects stay far below them.
+const MAX_ENTRIES: usize = 10_000;
+const MAX_ENTRY_BYTES: u64 = 100 * 1024 * 1024;
+const MAX_TOTAL_BYTES: u64 = 500 * 1024 * 1024;
+const MIB: u64 = 1024 * 1024;
+
+/// Reason of the backups holding asset files the project stopped using
+const UNUSED_ASSETS: &str = "unused-assets";
+
+/// Copy default project files into workspace if it doesn't hold a project yet
 pub fn ensure_default_project(workspace: &Path, resource_dir: &Path) {
-    let project_json = workspace.join("project.json");
-    if project_json.exists() {
-        eprintln!("[live-scratch] project.json already exists, skipping default project copy");
+    if layout::has_project(workspace) {
+        eprintln!("[live-scratch] project already exists, skipping default project copy");
         return;
     }
 
@@ -62,88 +83,354 @@ pub fn ensure_claude_md(workspace: &Path, resource_dir: &Path) {
     }
 }
 
-/// Build an SB3 (ZIP with STORE compression) from workspace files.
-/// Returns None if project.json has invalid JSON.
-pub fn build_sb3(workspace: &Path) -> Option<Vec<u8>> {
-    let entries = match fs::read_dir(workspace) {
-        Ok(e) => e,
+/// Why a build was rejected. `file`/`line`/`column` locate the first
+/// problem; `diagnostics` lists all of them.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildError {
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub diagnostics: Vec<validate::Diagnostic>,
+}
+
+impl BuildError {
+    fn from_diagnostics(diagnostics: Vec<validate::Diagnostic>) -> Self {
+        let first = &diagnostics[0];
+        let message = if diagnostics.len() > 1 {
+            format!("{} (and {} more)", first.message, diagnostics.len() - 1)
+        } else {
+            first.message.clone()
+        };
+        BuildError {
+            message,
+            file: Some(first.file.clone()),
+            line: first.line,
+            column: first.column,
+            diagnostics,
+        }
+    }
+
+    fn other(message: String) -> Self {
+        BuildError {
+            message,
+            file: None,
+            line: None,
+            column: None,
+            diagnostics: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.diagnostics.first() {
+            Some(first) if self.diagnostics.len() > 1 => {
+                write!(f, "{} (and {} more)", first, self.diagnostics.len() - 1)
+            }
+            Some(first) => write!(f, "{}", first),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Build an SB3 (ZIP, entries stored uncompressed) from workspace files:
+/// project.json and the costumes and sounds it references, nothing else.
+/// In the split layout project.json is assembled from project.meta.json and
+/// targets/*.json. Costumes and sounds stored under their own names
+/// (<target>/<name>.<ext>) are hashed and added under their md5ext, with
+/// assetId/md5ext in project.json rewritten to match. Other files are left
+/// out with a warning.
+/// The output is reproducible: project.json comes first, then the other
+/// entries sorted by name, all with the same fixed timestamp.
+/// Fails if any project file has invalid JSON or fails validation, which
+/// includes referencing an asset file that doesn't exist.
+pub fn build_sb3(workspace: &Path) -> Result<Vec<u8>, BuildError> {
+    build_sb3_with(workspace, None)
+}
+
+/// build_sb3 with the entries deflated at `level` (0 to 9), for exports:
+/// smaller files, at the cost of build time the live reload can't spare.
+pub fn build_sb3_compressed(workspace: &Path, level: u32) -> Result<Vec<u8>, BuildError> {
+    build_sb3_with(workspace, Some(level))
+}
+
+fn build_sb3_with(workspace: &Path, deflate_level: Option<u32>) -> Result<Vec<u8>, BuildError> {
+    let source = match layout::load_project(workspace) {
+        Ok(s) => s,
+        Err(diag) => {
+            log::error!("{}", diag);
+            return Err(BuildError::from_diagnostics(vec![diag]));
+        }
+    };
+
+    // Refuse to emit a project that vm.loadProject would choke on
+    let diagnostics = validate::check(workspace, &source);
+    if !diagnostics.is_empty() {
+        for diag in &diagnostics {
+            log::error!("Invalid project: {}", diag);
+        }
+        return Err(BuildError::from_diagnostics(diagnostics));
+    }
+
+    let mut project = source.project;
+    let named_assets = assets::resolve_named_assets(workspace, &mut project);
+    let project_json = match serde_json::to_vec(&project) {
+        Ok(j) => j,
         Err(err) => {
-            log::error!("Failed to read workspace: {}", err);
-            return None;
+            log::error!("Failed to serialize project.json: {}", err);
+            return Err(BuildError::other(format!("Failed to serialize project.json: {}", err)));
         }
     };
 
+    // Only the assets project.json references; validation made sure they
+    // exist. Sorted by name, so the same workspace always gives the same SB3.
+    let mut files = BTreeMap::new();
+    for (md5ext, content) in named_assets {
+        files.entry(md5ext).or_insert(content);
+    }
+    for asset_ref in assets::asset_refs(&project) {
+        if files.contains_key(&asset_ref.md5ext) {
+            continue;
+        }
+        let path = workspace.join(&asset_ref.md5ext);
+        match fs::read(&path) {
+            Ok(content) => {
+                files.insert(asset_ref.md5ext, content);
+            }
+            Err(err) => {
+                log::error!("Failed to read {:?}: {}", path, err);
+                return Err(BuildError::other(format!("Failed to read {:?}: {}", path, err)));
+            }
+        }
+    }
+
+    let unreferenced = assets::unreferenced_files(workspace, &project);
+    if !unreferenced.is_empty() {
+        log::warn!(
+            "[live-scratch] not in the SB3 (not referenced by project.json): {}",
+            unreferenced.join(", ")
+        );
+    }
+
     let mut buf = Vec::new();
     {
         let cursor = std::io::Cursor::new(&mut buf);
         let mut zip = zip::ZipWriter::new(cursor);
-        let options = zip::write::SimpleFileOptions::default()
-            .compression_method(zip::CompressionMethod::Stored);
+        let options = sb3_entry_options(deflate_level);
 
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if !path.is_file() {
-                continue;
-            }
-            let file_name = entry.file_name();
-            let file_name_str = file_name.to_string_lossy();
-
-            // Skip non-Scratch files (e.g. CLAUDE.md)
-            if file_name_str.ends_with(".md") {
-                continue;
-            }
-
-            let content = match fs::read(&path) {
-                Ok(c) => c,
-                Err(err) => {
-                    log::error!("Failed to read {:?}: {}", path, err);
-                    continue;
-                }
-            };
-
-            // Validate project.json
-            if file_name_str == "project.json" {
-                let json_str = String::from_utf8_lossy(&content);
-                if let Err(err) = serde_json::from_str::<serde_json::Value>(&json_str) {
-                    log::error!("JSON syntax error in project.json: {}", err);
-                    return None;
-                }
-            }
+        if let Err(err) = zip.start_file(layout::PROJECT_JSON, options) {
+            log::error!("Failed to start zip entry project.json: {}", err);
+            return Err(BuildError::other(format!("Failed to write SB3: {}", err)));
+        }
+        if let Err(err) = zip.write_all(&project_json) {
+            log::error!("Failed to write zip entry project.json: {}", err);
+            return Err(BuildError::other(format!("Failed to write SB3: {}", err)));
+        }
 
-            if let Err(err) = zip.start_file(file_name_str.as_ref(), options) {
-                log::error!("Failed to start zip entry {:?}: {}", file_name_str, err);
+        for (file_name, content) in &files {
+            if let Err(err) = zip.start_file(file_name.as_str(), options) {
+                log::error!("Failed to start zip entry {:?}: {}", file_name, err);
                 continue;
             }
-            if let Err(err) = zip.write_all(&content) {
-                log::error!("Failed to write zip entry {:?}: {}", file_name_str, err);
+            if let Err(err) = zip.write_all(content) {
+                log::error!("Failed to write zip entry {:?}: {}", file_name, err);
                 continue;
             }
         }
 
         if let Err(err) = zip.finish() {
             log::error!("Failed to finish zip: {}", err);
-            return None;
+            return Err(BuildError::other(format!("Failed to write SB3: {}", err)));
+        }
+    }
+
+    Ok(buf)
+}
+
+/// Options for the entries of the SB3s we write: stored, or deflated at
+/// the given level (0 stores), with fixed timestamp and permissions so
+/// their bytes depend only on the project.
+fn sb3_entry_options(deflate_level: Option<u32>) -> zip::write::SimpleFileOptions {
+    let options = zip::write::SimpleFileOptions::default()
+        .last_modified_time(zip::DateTime::default())
+        .unix_permissions(0o644);
+    match deflate_level {
+        Some(level) if level > 0 => options
+            .compression_method(zip::CompressionMethod::Deflated)
+            .compression_level(Some(i64::from(level.min(export::MAX_LEVEL)))),
+        _ => options.compression_method(zip::CompressionMethod::Stored),
+    }
+}
+
+/// Hash identifying a project's content: MD5 of its compact project.json.
+/// serde_json keeps object keys sorted, so the same project hashes the same
+/// whether it was built from the workspace or saved by the editor.
+pub fn project_hash(project: &serde_json::Value) -> String {
+    assets::md5_hex(&serde_json::to_vec(project).unwrap_or_default())
+}
+
+/// project_hash of the project.json inside an SB3.
+pub fn sb3_project_hash(data: &[u8]) -> Option<String> {
+    sb3_project(data).map(|project| project_hash(&project))
+}
+
+/// The project.json inside an SB3.
+pub fn sb3_project(data: &[u8]) -> Option<serde_json::Value> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).ok()?;
+    read_zip_project(&mut archive)
+}
+
+/// The workspace project as build_sb3 would send it (named assets
+/// resolved), without validating it.
+pub fn current_project(workspace: &Path) -> Result<serde_json::Value, String> {
+    let mut project = layout::load_project(workspace)
+        .map_err(|diag| diag.to_string())?
+        .project;
+    assets::resolve_named_assets(workspace, &mut project);
+    Ok(project)
+}
+
+/// Copy of an SB3 with its project.json replaced by `project`, keeping only
+/// the assets `project` references.
+pub fn replace_sb3_project(data: &[u8], project: &serde_json::Value) -> Result<Vec<u8>, String> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+        .map_err(|e| format!("Failed to open SB3 zip: {}", e))?;
+    let project_json = serde_json::to_vec(project)
+        .map_err(|e| format!("Failed to serialize project.json: {}", e))?;
+
+    let mut buf = Vec::new();
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut buf));
+    zip.start_file(layout::PROJECT_JSON, sb3_entry_options(None))
+        .and_then(|_| zip.write_all(&project_json).map_err(Into::into))
+        .map_err(|e| format!("Failed to write SB3: {}", e))?;
+    let referenced: HashSet<String> = assets::asset_refs(project)
+        .into_iter()
+        .map(|asset_ref| asset_ref.md5ext)
+        .collect();
+    for i in 0..archive.len() {
+        let file = archive
+            .by_index_raw(i)
+            .map_err(|e| format!("Failed to read zip entry {}: {}", i, e))?;
+        if !referenced.contains(file.name()) {
+            continue;
         }
+        zip.raw_copy_file(file)
+            .map_err(|e| format!("Failed to write SB3: {}", e))?;
     }
+    zip.finish().map_err(|e| format!("Failed to write SB3: {}", e))?;
+    Ok(buf)
+}
 
-    Some(buf)
+/// Validate the workspace project without building it.
+pub fn validate_workspace(workspace: &Path) -> Vec<validate::Diagnostic> {
+    match layout::load_project(workspace) {
+        Ok(source) => validate::check(workspace, &source),
+        Err(diag) => vec![diag],
+    }
+}
+
+/// Replace the workspace project with the one in an SB3 file. The files
+/// being replaced are archived first (see backup::archive_workspace), so
+/// files of the previous project don't linger in the workspace.
+/// Returns the backup folder, if anything was archived.
+pub fn open_sb3(workspace: &Path, data: &[u8]) -> Result<Option<PathBuf>, String> {
+    // Check the file before touching the workspace
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+        .map_err(|e| format!("Not a valid SB3 file: {}", e))?;
+    if archive.by_name(layout::PROJECT_JSON).is_err() {
+        return Err(format!("Not a valid SB3 file: no {}", layout::PROJECT_JSON));
+    }
+    check_archive(&mut archive)?;
+
+    // Archiving and extracting are one update: after a crash the workspace
+    // holds either the old or the new project
+    let mut tx = Transaction::begin(workspace)?;
+    let backup = backup::archive_workspace(&mut tx, "open")?;
+    // Hash-named copies were archived with everything else
+    let (count, _) = stage_sb3(&mut tx, data)?;
+    tx.commit()?;
+    backup::prune(workspace, "open");
+    log::info!("Extracted {} files to workspace", count);
+    Ok(backup)
 }
 
 /// Extract an SB3 (ZIP) into the workspace directory.
-/// project.json is pretty-printed.
-pub fn extract_sb3(workspace: &Path, data: &[u8]) {
-    fs::create_dir_all(workspace).ok();
+/// project.json is pretty-printed, or split into target files when the
+/// workspace uses the split layout. Costumes and sounds are written under
+/// their own names (<target>/<name>.<ext>) instead of their md5ext.
+/// Asset files of the replaced project that the new one no longer uses
+/// (deleted or renamed costumes and sounds), and hash-named copies in the
+/// root that the named files replace, are moved to
+/// .live-scratch/backups/<timestamp>-unused-assets/ in the same update.
+pub fn extract_sb3(workspace: &Path, data: &[u8]) -> Result<(), String> {
+    fs::create_dir_all(workspace)
+        .map_err(|e| format!("Failed to create {:?}: {}", workspace, e))?;
+    let mut unused = match (layout::read_project(workspace), sb3_project(data)) {
+        (Ok(old), Some(new)) => assets::dropped_assets(workspace, &old, &new),
+        _ => Vec::new(),
+    };
+    let mut tx = Transaction::begin(workspace)?;
+    let (count, replaced) = stage_sb3(&mut tx, data)?;
+    unused.extend(replaced);
+    unused.sort();
+    unused.dedup();
+    let backup = backup::archive_files(&mut tx, &unused, UNUSED_ASSETS)?;
+    tx.commit()?;
+    if backup.is_some() {
+        backup::prune(workspace, UNUSED_ASSETS);
+    }
+    log::info!("Extracted {} files to workspace", count);
+    Ok(())
+}
+
+/// Move the costume and sound files the workspace project doesn't use
+/// (see assets::orphaned_assets) to
+/// .live-scratch/backups/<timestamp>-unused-assets/. Returns the moved
+/// files, workspace-relative with `/`.
+pub fn clean_unused_assets(workspace: &Path) -> Result<Vec<String>, String> {
+    let mut project = layout::read_project(workspace)?;
+    assets::resolve_named_assets(workspace, &mut project);
+    let orphaned = assets::orphaned_assets(workspace, &project);
+    if !orphaned.is_empty() {
+        let mut tx = Transaction::begin(workspace)?;
+        backup::archive_files(&mut tx, &orphaned, UNUSED_ASSETS)?;
+        tx.commit()?;
+        backup::prune(workspace, UNUSED_ASSETS);
+    }
+    Ok(orphaned
+        .iter()
+        .map(|path| path.to_string_lossy().replace('\\', "/"))
+        .collect())
+}
 
+/// Stage the files of an SB3 in a workspace update. Returns the number of
+/// zip entries staged and the hash-named files in the workspace root
+/// (<md5ext>) that a costume or sound written under its own name replaces.
+/// Fails without staging anything if the SB3 breaks the limits or has
+/// entries that would land outside the workspace.
+fn stage_sb3(tx: &mut Transaction, data: &[u8]) -> Result<(usize, Vec<PathBuf>), String> {
+    let workspace = tx.workspace().to_path_buf();
     let cursor = std::io::Cursor::new(data);
-    let mut archive = match zip::ZipArchive::new(cursor) {
-        Ok(a) => a,
-        Err(err) => {
-            log::error!("Failed to open SB3 zip: {}", err);
-            return;
+    let mut archive =
+        zip::ZipArchive::new(cursor).map_err(|e| format!("Failed to open SB3 zip: {}", e))?;
+    check_archive(&mut archive)?;
+
+    // Map each md5ext to the human-readable paths of the assets using it
+    let mut named_paths: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    if let Some(project) = read_zip_project(&mut archive) {
+        for asset_ref in assets::asset_refs(&project) {
+            named_paths
+                .entry(asset_ref.md5ext)
+                .or_default()
+                .push(asset_ref.named_path);
         }
-    };
+    }
 
     let mut count = 0;
+    let mut replaced = Vec::new();
+    // The sizes in the zip headers may lie, so count what is actually read
+    let mut total = 0;
     for i in 0..archive.len() {
         let mut file = match archive.by_index(i) {
             Ok(f) => f,
@@ -158,34 +445,193 @@ pub fn extract_sb3(workspace: &Path, data: &[u8]) {
         }
 
         let name = file.name().to_string();
-        let out_path = workspace.join(&name);
+        let out_path = workspace.join(entry_path(&name)?);
 
         let mut content = Vec::new();
-        if let Err(err) = file.read_to_end(&mut content) {
+        if let Err(err) = (&mut file).take(MAX_ENTRY_BYTES + 1).read_to_end(&mut content) {
             log::error!("Failed to read zip entry {:?}: {}", name, err);
             continue;
         }
+        check_size(&name, content.len() as u64, &mut total)?;
 
-        if name == "project.json" {
-            // Pretty-print project.json
+        if name == layout::PROJECT_JSON {
             match serde_json::from_slice::<serde_json::Value>(&content) {
-                Ok(json) => {
-                    let pretty = serde_json::to_string_pretty(&json).unwrap_or_default();
-                    if let Err(err) = fs::write(&out_path, pretty) {
-                        log::error!("Failed to write {:?}: {}", out_path, err);
-                    }
-                }
-                Err(_) => {
-                    if let Err(err) = fs::write(&out_path, &content) {
-                        log::error!("Failed to write {:?}: {}", out_path, err);
-                    }
-                }
+                Ok(json) => layout::write_project(tx, &json)?,
+                Err(_) => tx.write(&out_path, &content)?,
             }
-        } else if let Err(err) = fs::write(&out_path, &content) {
-            log::error!("Failed to write {:?}: {}", out_path, err);
+        } else if let Some(paths) = named_paths.get(&name) {
+            for rel in paths {
+                let rel = entry_path(&rel.to_string_lossy())?;
+                tx.write(&workspace.join(rel), &content)?;
+            }
+            if out_path.is_file() {
+                replaced.push(PathBuf::from(&name));
+            }
+        } else {
+            tx.write(&out_path, &content)?;
         }
 
         count += 1;
     }
-    log::info!("Extracted {} files to workspace", count);
+    Ok((count, replaced))
+}
+
+fn read_zip_project<R: std::io::Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> Option<serde_json::Value> {
+    let file = archive.by_name(layout::PROJECT_JSON).ok()?;
+    let mut content = Vec::new();
+    file.take(MAX_ENTRY_BYTES).read_to_end(&mut content).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+/// Check the entry count, the entry names and the sizes declared in the
+/// headers of an SB3 before extracting anything from it.
+fn check_archive<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> Result<(), String> {
+    if archive.len() > MAX_ENTRIES {
+        return Err(format!(
+            "SB3 has too many files ({}, the limit is {})",
+            archive.len(),
+            MAX_ENTRIES
+        ));
+    }
+    let mut total = 0;
+    for i in 0..archive.len() {
+        let file = archive
+            .by_index_raw(i)
+            .map_err(|e| format!("Failed to read zip entry {}: {}", i, e))?;
+        if file.is_dir() {
+            continue;
+        }
+        entry_path(file.name())?;
+        check_size(file.name(), file.size(), &mut total)?;
+    }
+    Ok(())
+}
+
+/// Add the uncompressed size of an entry to `total`, failing if either
+/// goes over its limit.
+fn check_size(name: &str, size: u64, total: &mut u64) -> Result<(), String> {
+    if size > MAX_ENTRY_BYTES {
+        return Err(format!(
+            "{:?} in the SB3 is too large (over {} MiB)",
+            name,
+            MAX_ENTRY_BYTES / MIB
+        ));
+    }
+    *total += size;
+    if *total > MAX_TOTAL_BYTES {
+        return Err(format!(
+            "SB3 is too large (over {} MiB uncompressed)",
+            MAX_TOTAL_BYTES / MIB
+        ));
+    }
+    Ok(())
+}
+
+/// The workspace-relative path an SB3 entry extracts to. Absolute names,
+/// `..` and drive prefixes are rejected so no entry can be written outside
+/// the workspace (zip slip), as are entries inside the app's state folder.
+fn entry_path(name: &str) -> Result<PathBuf, String> {
+    let unsafe_name = || format!("SB3 contains an unsafe file name: {:?}", name);
+    // Treat both separators alike whatever the platform
+    let normalized = name.replace('\\', "/");
+    if normalized.contains(':') {
+        return Err(unsafe_name());
+    }
+    let mut path = PathBuf::new();
+    for component in Path::new(&normalized).components() {
+        match component {
+            Component::Normal(part) => {
+                if path.as_os_str().is_empty() && part == status::STATE_DIR {
+                    return Err(unsafe_name());
+                }
+                path.push(part);
+            }
+            Component::CurDir => {}
+            Component::RootDir | Component::ParentDir | Component::Prefix(_) => {
+                return Err(unsafe_name())
+            }
+        }
+    }
+    if path.as_os_str().is_empty() {
+        return Err(unsafe_name());
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory zip with an empty file for each name.
+    fn archive_with(names: &[&str]) -> zip::ZipArchive<std::io::Cursor<Vec<u8>>> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for name in names {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+        }
+        zip::ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn entry_path_keeps_names_inside_the_workspace() {
+        assert_eq!(
+            entry_path("project.json").unwrap(),
+            PathBuf::from("project.json")
+        );
+        assert_eq!(
+            entry_path("./Sprite1/cat.svg").unwrap(),
+            Path::new("Sprite1").join("cat.svg")
+        );
+        assert_eq!(
+            entry_path("Sprite1\\cat.svg").unwrap(),
+            Path::new("Sprite1").join("cat.svg")
+        );
+    }
+
+    #[test]
+    fn entry_path_rejects_names_escaping_the_workspace() {
+        for name in [
+            "../evil.svg",
+            "Sprite1/../../evil.svg",
+            "..\\evil.svg",
+            "/etc/passwd",
+            "\\evil.svg",
+            "C:evil.svg",
+            "C:/Windows/evil.svg",
+            "C:\\Windows\\evil.svg",
+            "",
+            "./",
+        ] {
+            assert!(entry_path(name).is_err(), "{:?} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn entry_path_rejects_the_state_folder() {
+        for name in [
+            ".live-scratch/journal/journal.json",
+            "./.live-scratch/status.json",
+        ] {
+            assert!(entry_path(name).is_err(), "{:?} was accepted", name);
+        }
+        // Only at the top level
+        assert!(entry_path("Sprite1/.live-scratch/cat.svg").is_ok());
+    }
+
+    #[test]
+    fn check_archive_rejects_unsafe_entries() {
+        assert!(check_archive(&mut archive_with(&["project.json", "cat.svg"])).is_ok());
+        for name in [
+            "../evil.svg",
+            "/evil.svg",
+            "C:evil.svg",
+            ".live-scratch/status.json",
+        ] {
+            let result = check_archive(&mut archive_with(&["project.json", name]));
+            assert!(result.is_err(), "{:?} was accepted", name);
+        }
+    }
 }